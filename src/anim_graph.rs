@@ -0,0 +1,164 @@
+use crate::animations::{AnimationState, DirectionalSprite, TopDownStates};
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// Gameplay never picks clips itself, it sets parameters (`moving`, `hurt` ...) on
+// `AnimationParams` and each entity's graph works out which state that puts it in
+
+// =======
+// STRUCTS
+// =======
+
+#[derive(Deserialize, Clone)]
+pub struct GraphState {
+    #[serde(default)]
+    pub clip: Option<String>, // the state's own name when missing
+    #[serde(default)]
+    pub priority: i32,        // higher wins, and can cut a lower one's `min_s` short
+    #[serde(default)]
+    pub when: Vec<String>,    // parameters that all have to be set, `!name` for not set
+    #[serde(default)]
+    pub min_s: f32,           // stays at least this long once entered, even if `when` stops holding
+    #[serde(default)]
+    pub to: Option<Vec<String>>, // states it can go to, any when missing
+}
+
+#[derive(Deserialize, Clone)]
+pub struct GraphConfig {
+    pub initial: String,
+    pub states: BTreeMap<String, GraphState>,
+}
+
+impl GraphConfig {
+    /// what sheets without a `graph` get, the clips the top-down gameplay has always used
+    pub fn top_down() -> GraphConfig {
+        let state = |priority: i32, when: &[&str], min_s: f32| GraphState {
+            clip: None,
+            priority,
+            when: when.iter().map(|name| name.to_string()).collect(),
+            min_s,
+            to: None,
+        };
+        let states = [
+            (TopDownStates::Prone, state(0, &[], 0.0)),
+            (TopDownStates::Moving, state(1, &["moving"], 0.0)),
+            (TopDownStates::TurningLeft, state(2, &["turning_left"], 0.0)),
+            (TopDownStates::TurningRight, state(2, &["turning_right"], 0.0)),
+            (TopDownStates::Hurt, state(3, &["hurt"], 0.3)),
+        ];
+        GraphConfig {
+            initial: TopDownStates::Prone.as_ref().to_string(),
+            states: states.into_iter().map(|(name, state)| (name.as_ref().to_string(), state)).collect(),
+        }
+    }
+}
+
+// ==========
+// COMPONENTS
+// ==========
+
+/// What gameplay knows about an entity. Flags stay until cleared, triggers last until the graph has seen them once
+#[derive(Component, Default)]
+pub struct AnimationParams {
+    flags: HashSet<String>,
+    triggers: HashSet<String>,
+}
+
+impl AnimationParams {
+    pub fn set(&mut self, name: &str, on: bool) {
+        if on { self.flags.insert(name.to_string()); } 
+        else { self.flags.remove(name); }
+    }
+
+    pub fn trigger(&mut self, name: &str) {
+        self.triggers.insert(name.to_string());
+    }
+
+    #[inline]
+    fn holds(&self, condition: &str) -> bool {
+        match condition.strip_prefix('!') {
+            Some(name) => !self.flags.contains(name) && !self.triggers.contains(name),
+            None => self.flags.contains(condition) || self.triggers.contains(condition),
+        }
+    }
+}
+
+
+#[derive(Component)]
+pub struct AnimationGraph {
+    states: Vec<(String, GraphState)>, // highest priority first
+    current: usize,
+    elapsed: f32,
+}
+
+impl AnimationGraph {
+    pub fn new(config: GraphConfig) -> Self {
+        let mut states: Vec<(String, GraphState)> = config.states.into_iter().collect();
+        states.sort_by_key(|(_, state)| std::cmp::Reverse(state.priority)); // stable, ties stay alphabetical
+        let current = states.iter().position(|(name, _)| *name == config.initial).unwrap_or(0);
+        AnimationGraph { states, current, elapsed: 0.0 }
+    }
+
+    pub fn current(&self) -> &str {
+        &self.states[self.current].0
+    }
+
+    /// whether some state waits on `param`, so setting it shows up at all
+    pub fn reacts_to(&self, param: &str) -> bool {
+        self.states.iter().any(|(_, state)| state.when.iter().any(|condition| condition == param))
+    }
+
+    /// whether the state it's in right now is one of those
+    pub fn is_in_state_for(&self, param: &str) -> bool {
+        self.states[self.current].1.when.iter().any(|condition| condition == param)
+    }
+
+    fn clip(&self) -> &str {
+        let (name, state) = &self.states[self.current];
+        state.clip.as_deref().unwrap_or(name)
+    }
+
+    /// moves time on, switches state if the parameters say so and uses up the triggers
+    pub fn step(&mut self, params: &mut AnimationParams, dt: f32) {
+        self.elapsed += dt;
+        if let Some(next) = self.next(params) {
+            self.current = next;
+            self.elapsed = 0.0;
+        }
+        params.triggers.clear();
+    }
+
+    /// the state the parameters ask for, if it's different and allowed to take over
+    fn next(&self, params: &AnimationParams) -> Option<usize> {
+        let (_, current) = &self.states[self.current];
+        let current_holds = current.when.iter().all(|condition| params.holds(condition));
+        let settled = self.elapsed >= current.min_s;
+
+        let (i, (_, best)) = self.states.iter().enumerate()
+            .filter(|(i, _)| *i != self.current)
+            .filter(|(_, (_, state))| state.when.iter().all(|condition| params.holds(condition)))
+            .find(|(_, (name, _))| current.to.as_ref().map_or(true, |to| to.contains(name)))?;
+
+        // a higher priority cuts in right away, anything else waits out `min_s`
+        // and only takes over once the current state stops holding
+        let takes_over = best.priority > current.priority || (settled && !current_holds);
+        takes_over.then_some(i)
+    }
+}
+
+// =======
+// SYSTEMS
+// =======
+
+pub fn run(
+    time: Res<Time>,
+    mut query: Query<(&mut AnimationGraph, &mut AnimationParams, &mut AnimationState), Without<DirectionalSprite>>,
+) {
+    for (mut graph, mut params, mut state) in &mut query {
+        graph.step(&mut params, time.delta_seconds());
+        let clip = graph.clip().to_string();
+        state.change_if_its_not(clip);
+    }
+}
@@ -1,14 +1,26 @@
 use serde::Deserialize;
 use bevy::{
     prelude::{Deref, DerefMut}, 
-    sprite::TextureAtlas, 
+    math::{ Quat, Rect, Vec2, Vec3 },
+    transform::components::Transform,
+    sprite::{ TextureAtlas, TextureAtlasLayout }, 
     time::{Time, Timer},
     ecs::{ 
         component::Component, 
+        entity::Entity,
+        event::{ Event, EventWriter },
+        query::With,
         system::{ Query, Res } 
     }, 
 };
+use crate::camera::{CameraMode, screen_rotation};
+use crate::gamedata::GameData;
+use crate::player::Player;
+use crate::anim_graph::{AnimationGraph, GraphConfig};
+use crate::atlas::PackedAtlas;
 use std::{
+    f32::consts::TAU,
+    time::Duration,
     io::BufReader,
     collections::HashMap, 
     fs::File
@@ -21,32 +33,124 @@ use std::{
 #[derive(Deserialize, Clone, Component)]
 pub struct SpriteSheetConfig {
     pub path: String,
+    // a packed Aseprite / TexturePacker JSON next to the image, replaces the grid below
+    #[serde(default)]
+    pub atlas: Option<String>,
+    #[serde(default)]
     pub frame_width: f32,
+    #[serde(default)]
     pub frame_height: f32,
+    #[serde(default)]
     pub columns: usize,
+    #[serde(default)]
     pub rows: usize,
+    #[serde(default)]
     pub padding_x: f32,
+    #[serde(default)]
     pub padding_y: f32,
     pub duration_s: f32,
-    pub animations: Animations
+    // with an atlas the clips come from its tags, entries here only add modes, events ...
+    #[serde(default)]
+    pub animations: HashMap<String, Indices>,
+    #[serde(default = "SpriteSheetConfig::default_fallback")]
+    pub fallback: String, // played when something asks for a clip the sheet doesn't have
+    #[serde(default)]
+    pub directional: Option<DirectionalConfig>,
+    #[serde(default)]
+    pub graph: Option<GraphConfig>, // the top-down one when missing
+    #[serde(skip)]
+    pub packed: Option<(Vec2, Vec<Rect>)>, // atlas size and frames, filled in from `atlas`
+}
+
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+pub enum DirectionSource {
+    Facing,   // where the entity's transform points
+    Velocity, // where it's actually going, keeps the last direction while standing still
+}
+
+/// Sheets drawn from several angles (isometric and the like) pick their clip from which way
+/// the entity is headed on screen. Clip names are `idle` / `moving` with `{dir}` swapped for
+/// one of `directions`
+#[derive(Deserialize, Clone)]
+pub struct DirectionalConfig {
+    pub directions: Vec<String>, // counter-clockwise, evenly spaced, usually 4 or 8
+    pub start_deg: f32,          // where the first one points, counter-clockwise from the top of the screen
+    pub source: DirectionSource,
+    pub idle: String,            // e.g. "Idle{dir}"
+    pub moving: String,          // e.g. "Walk{dir}", can be the same as `idle`
+}
+
+impl DirectionalConfig {
+    /// which of `directions` is closest to `angle` (radians, counter-clockwise from up)
+    fn direction(&self, angle: f32) -> &str {
+        let n = self.directions.len();
+        let step = TAU / n as f32;
+        let i = ((angle - self.start_deg.to_radians()) / step).round().rem_euclid(n as f32) as usize;
+        &self.directions[i % n]
+    }
 }
 
 impl SpriteSheetConfig {
+    fn default_fallback() -> String { String::from("Prone") }
+
+    pub fn graph(&self) -> AnimationGraph {
+        AnimationGraph::new(self.graph.clone().unwrap_or_else(GraphConfig::top_down))
+    }
+
+    pub fn animations(&self) -> Animations {
+        Animations { clips: self.animations.clone(), fallback: self.fallback.clone(), duration_s: self.duration_s }
+    }
 
     pub fn new(json_path: &str) -> Self {
         let reader = BufReader::new(File::open(json_path).unwrap());
-        let sheet = serde_json::from_reader(reader).unwrap();
-        sheet
+        let sheet: SpriteSheetConfig = serde_json::from_reader(reader).unwrap();
+        sheet.with_atlas()
     }
 
     pub fn new_vec(json_path: &str) -> Vec<SpriteSheetConfig> {
         let reader = BufReader::new(File::open(json_path).unwrap());
         let sheets: Vec<SpriteSheetConfig> = serde_json::from_reader(reader).unwrap();
-        sheets
+        sheets.into_iter().map(SpriteSheetConfig::with_atlas).collect()
+    }
+
+    /// reads the packed atlas, if there is one, and merges its clips with the ones in the config
+    fn with_atlas(mut self) -> Self {
+        let Some(path) = self.atlas.as_ref() else { return self; };
+        let atlas = PackedAtlas::load(path);
+
+        for (name, packed) in atlas.clips {
+            match self.animations.get_mut(&name) {
+                Some(clip) => {
+                    clip.first = packed.first;
+                    clip.last = packed.last;
+                    if clip.frame_durations_s.is_empty() && clip.duration_s.is_none() {
+                        clip.frame_durations_s = packed.frame_durations_s;
+                    }
+                    if clip.mode == LoopMode::Loop { clip.mode = packed.mode; }
+                },
+                None => { self.animations.insert(name, packed); },
+            }
+        }
+        self.packed = Some((atlas.size, atlas.frames));
+        self
     }
 
-    pub fn player() -> Self {
-        SpriteSheetConfig::new("config/player_sprites.json")
+    pub fn layout(&self) -> TextureAtlasLayout {
+        match &self.packed {
+            Some((size, frames)) => {
+                let mut layout = TextureAtlasLayout::new_empty(*size);
+                for frame in frames { layout.add_texture(*frame); }
+                layout
+            },
+            None => TextureAtlasLayout::from_grid(
+                Vec2::new(self.frame_width, self.frame_height), 
+                self.columns, 
+                self.rows,
+                Some(Vec2::new(self.padding_x, self.padding_y)), 
+                None
+            ),
+        }
     }
 
     pub fn enemies() -> Vec<SpriteSheetConfig> {
@@ -55,11 +159,32 @@ impl SpriteSheetConfig {
 }
 
 
-// honestly get rid of this and put directly in AnimationComponent
-#[derive(Component, Deserialize, Deref, DerefMut, Clone)]
-pub struct Animations(pub HashMap<String, Indices>); 
+/// every clip a sheet has, by name. Any name works, the sheet's config decides what exists
+#[derive(Component, Clone)]
+pub struct Animations {
+    pub clips: HashMap<String, Indices>,
+    pub fallback: String,
+    pub duration_s: f32, // frame time for clips that don't set their own
+}
 
-#[derive(Component, PartialEq, Clone, Copy)]
+impl Animations {
+    /// the clip called `name`, or the fallback clip if the sheet doesn't have one
+    pub fn get(&self, name: &str) -> Option<&Indices> {
+        self.clips.get(name).or_else(|| self.clips.get(&self.fallback))
+    }
+
+    /// clips that play out before anything else gets to change the animation
+    fn one_shots(&self) -> Vec<String> {
+        self.clips.iter()
+            .filter(|(_, clip)| clip.mode == LoopMode::OnceReturn)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+/// The clips the top-down gameplay code asks for. Just names, a sheet is free to have
+/// others (`Attack`, `Death`, isometric `NE` / `NW` ...) and anything can ask for those by string
+#[derive(PartialEq, Clone, Copy)]
 pub enum TopDownStates {
     Prone, 
     Moving, 
@@ -68,35 +193,77 @@ pub enum TopDownStates {
     Hurt,
 }
 
-/* 
-#[derive(Component, PartialEq, Clone, Copy)]
-pub enum IsometricStates {
-    Prone, Moving, NE, NW, SE, SW, North, South, East, West
+impl AsRef<str> for TopDownStates {
+    fn as_ref(&self) -> &str {
+        match self {
+            TopDownStates::Prone => "Prone",
+            TopDownStates::Moving => "Moving",
+            TopDownStates::TurningLeft => "TurningLeft",
+            TopDownStates::TurningRight => "TurningRight",
+            TopDownStates::Hurt => "Hurt",
+        }
+    }
 }
-*/
 
 #[derive(Component)]
 pub struct AnimationState {
-    pub current: TopDownStates, // honestly change this to be a &str, allows more flexibility in making different animations
+    pub current: String, // name of a clip in `Animations`
     pub has_changed: bool,
+    pub frame: usize,    // how far into the clip, 0 is `first`
+    pub forward: bool,   // which way a ping-pong clip is going
+    pub return_to: Option<String>, // what to go back to once a one-shot clip is done
+    pub done: bool,      // a once-and-hold clip has played all the way through its last frame
+    one_shots: Vec<String>,
 }
 
 impl AnimationState {
-    pub fn new(current: TopDownStates, has_changed: bool) -> AnimationState {
-        AnimationState { current, has_changed } 
+    pub fn new(current: impl AsRef<str>, animations: &Animations) -> AnimationState {
+        AnimationState { 
+            current: current.as_ref().to_string(), 
+            has_changed: true,
+            frame: 0,
+            forward: true,
+            return_to: None,
+            done: false,
+            one_shots: animations.one_shots(),
+        } 
     }
     
     #[inline]
-    pub fn is(&self, animation: TopDownStates) -> bool {
-        self.current == animation
+    pub fn is(&self, animation: impl AsRef<str>) -> bool {
+        self.current == animation.as_ref()
     }
 
+    /// While a once-then-return clip plays, asking for another clip only changes what it returns to
     #[inline]
-    pub fn change_if_its_not(&mut self, new: TopDownStates) {
-        if self.current != new {
-            self.current = new;
-            self.has_changed = true;
+    pub fn change_if_its_not(&mut self, new: impl AsRef<str>) {
+        let new = new.as_ref();
+        if self.is(new) { return; }
+
+        if self.one_shots.contains(&self.current) {
+            self.return_to = Some(new.to_string());
+            return;
         }
+        if self.one_shots.iter().any(|name| name == new) {
+            self.return_to = Some(std::mem::take(&mut self.current));
+        }
+        self.current = new.to_string();
+        self.has_changed = true;
+        self.done = false;
+    }
+}
+
+/// picks the clip for sheets with a `directional` config
+#[derive(Component)]
+pub struct DirectionalSprite {
+    pub config: DirectionalConfig,
+    pub last_pos: Option<Vec2>,
+    pub angle: f32, // the last direction it was headed, for velocity sheets standing still
+}
+
+impl DirectionalSprite {
+    pub fn new(config: DirectionalConfig) -> Self {
+        DirectionalSprite { angle: config.start_deg.to_radians(), config, last_pos: None }
     }
 }
 
@@ -104,42 +271,149 @@ impl AnimationState {
 #[derive(Component, Deref, DerefMut, Clone)]
 pub struct AnimationTimer(pub Timer);
 
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum LoopMode {
+    #[default]
+    Loop,       // first to last, over and over
+    PingPong,   // first to last and back again
+    OnceHold,   // plays once and stays on the last frame
+    OnceReturn, // plays once and goes back to whatever was playing before
+}
+
 #[derive(Component, Deserialize, Clone)]
 pub struct Indices {
     pub first: usize,
     pub last: usize,
+    #[serde(default)]
+    pub duration_s: Option<f32>,    // frame time for this clip, the sheet's when missing
+    #[serde(default)]
+    pub frame_durations_s: Vec<f32>, // one per frame, wins over `duration_s`
+    #[serde(default)]
+    pub mode: LoopMode,
+    #[serde(default)]
+    pub events: Vec<ClipEvent>,
+}
+
+/// `name` goes out as an `AnimationEvent` every time the clip reaches `frame` (0 is `first`)
+#[derive(Deserialize, Clone)]
+pub struct ClipEvent {
+    pub frame: usize,
+    pub name: String,
 }
 
+/// sent by `animate` when an entity's clip reaches a frame with an event on it
+#[derive(Event, Clone)]
+pub struct AnimationEvent {
+    pub entity: Entity,
+    pub name: String,
+}
+
+impl Indices {
+    #[inline]
+    fn len(&self) -> usize {
+        self.last.saturating_sub(self.first) + 1
+    }
+
+    fn events_on(&self, frame: usize) -> impl Iterator<Item = &ClipEvent> {
+        self.events.iter().filter(move |event| event.frame == frame)
+    }
+
+    #[inline]
+    fn frame_duration(&self, frame: usize, default_s: f32) -> Duration {
+        let seconds = self.frame_durations_s.get(frame).copied()
+            .or(self.duration_s)
+            .unwrap_or(default_s);
+        Duration::from_secs_f32(seconds.max(0.001))
+    }
+}
+
+
+/// directions are worked out on screen, so they match what the player sees
+pub fn pick_direction(
+    gamedata: Res<GameData>,
+    camera_mode: Res<CameraMode>,
+    player_q: Query<&Transform, With<Player>>,
+    mut query: Query<(&Transform, &mut DirectionalSprite, &mut AnimationState)>,
+) {
+    let to_screen = player_q.get_single()
+        .map_or(Quat::IDENTITY, |pt| screen_rotation(&gamedata, *camera_mode, pt))
+        .inverse();
+
+    for (t, mut sprite, mut state) in &mut query {
+        let pos = t.translation.truncate();
+        let velocity = sprite.last_pos.map_or(Vec2::ZERO, |last| pos - last);
+        sprite.last_pos = Some(pos);
+        let moving = velocity.length_squared() > f32::EPSILON;
+
+        let heading = match sprite.config.source {
+            DirectionSource::Facing => Some(t.rotation.mul_vec3(Vec3::Y).truncate()),
+            DirectionSource::Velocity => moving.then_some(velocity),
+        };
+        if let Some(heading) = heading {
+            let on_screen = to_screen.mul_vec3(heading.extend(0.0));
+            sprite.angle = (-on_screen.x).atan2(on_screen.y);
+        }
+
+        let pattern = if moving { &sprite.config.moving } else { &sprite.config.idle };
+        let clip = pattern.replace("{dir}", sprite.config.direction(sprite.angle));
+        state.change_if_its_not(clip);
+    }
+}
+
+
+/// Runs on the fixed tick with the rest of the simulation, since guns can fire off its events
 pub fn animate(
     time: Res<Time>,
-    mut query: Query<(&mut AnimationTimer, &mut TextureAtlas, &Animations, &mut AnimationState)>,
+    mut events: EventWriter<AnimationEvent>,
+    mut query: Query<(Entity, &mut AnimationTimer, &mut TextureAtlas, &Animations, &mut AnimationState)>,
 ) {
-    for (mut timer, mut atlas, states, mut state) in &mut query {
-
-        let indices = match state.current {
-            TopDownStates::Hurt => states.0.get("Hurt").unwrap(),
-            TopDownStates::Moving => states.0.get("Moving").unwrap(),
-            TopDownStates::Prone => states.0.get("Prone").unwrap(),
-            TopDownStates::TurningLeft => states.0.get("TurningLeft").unwrap(),
-            TopDownStates::TurningRight => states.0.get("TurningRight").unwrap(),
+    for (entity, mut timer, mut atlas, states, mut state) in &mut query {
+        let mut send = |clip: &Indices, frame: usize| {
+            for event in clip.events_on(frame) {
+                events.send(AnimationEvent { entity, name: event.name.clone() });
+            }
         };
 
+        let Some(clip) = states.get(&state.current) else { continue; }; // no such clip and no fallback either
+
         if state.has_changed { 
-            let duration = timer.duration();
-            timer.tick(duration); 
-            atlas.index = indices.first;
-        } else { 
-            timer.tick(time.delta()); 
+            state.has_changed = false;
+            state.done = false;
+            state.frame = 0;
+            state.forward = true;
+            atlas.index = clip.first;
+            timer.set_duration(clip.frame_duration(0, states.duration_s));
+            timer.reset();
+            send(clip, 0);
+            continue;
         }
 
-        if timer.finished() {
-            if atlas.index >= indices.last { 
-                atlas.index = indices.first; 
-            } else { 
-                atlas.index += 1; 
-            }
-        } 
+        timer.tick(time.delta()); 
+        if !timer.just_finished() { continue; }
+
+        let frames = clip.len();
+        let last = frames - 1;
+        let held = clip.mode == LoopMode::OnceHold && state.frame == last;
+        if held { state.done = true; }
+        state.frame = match clip.mode {
+            LoopMode::Loop => (state.frame + 1) % frames,
+            LoopMode::PingPong if frames == 1 => 0,
+            LoopMode::PingPong => {
+                if state.frame == last { state.forward = false; } 
+                else if state.frame == 0 { state.forward = true; }
+                if state.forward { state.frame + 1 } else { state.frame - 1 }
+            },
+            LoopMode::OnceHold => (state.frame + 1).min(last),
+            LoopMode::OnceReturn if state.frame == last => {
+                state.current = state.return_to.take().unwrap_or_else(|| states.fallback.clone());
+                state.has_changed = true;
+                continue;
+            },
+            LoopMode::OnceReturn => state.frame + 1,
+        };
 
-        state.has_changed = false;
+        atlas.index = clip.first + state.frame;
+        timer.set_duration(clip.frame_duration(state.frame, states.duration_s));
+        if !held { send(clip, state.frame); }
     }
 }
@@ -0,0 +1,234 @@
+use crate::animations::{Indices, LoopMode};
+use bevy::math::{Rect, Vec2};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+// Packed atlases as Aseprite and TexturePacker export them ("Hash" or "Array" JSON).
+// Clips come from Aseprite's frame tags, TexturePacker's `animations`, or failing
+// both from frame names (`walk_01.png`, `walk_02.png` ... make a `walk` clip)
+
+// =======
+// STRUCTS
+// =======
+
+#[derive(Deserialize)]
+struct FrameRect {
+    x: f32,
+    y: f32,
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct PackedFrame {
+    #[serde(default)]
+    filename: String, // only in "Array" exports, "Hash" ones key the frames by it
+    frame: FrameRect,
+    #[serde(default)]
+    duration: Option<f32>, // milliseconds, Aseprite only
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Frames {
+    Array(Vec<PackedFrame>),
+    Hash(HashMap<String, PackedFrame>),
+}
+
+#[derive(Deserialize)]
+struct FrameTag {
+    name: String,
+    from: usize,
+    to: usize,
+    #[serde(default)]
+    direction: String, // forward, reverse or pingpong
+}
+
+#[derive(Deserialize)]
+struct Size {
+    w: f32,
+    h: f32,
+}
+
+#[derive(Deserialize)]
+struct Meta {
+    size: Size,
+    #[serde(default, rename = "frameTags")]
+    frame_tags: Vec<FrameTag>,
+}
+
+#[derive(Deserialize)]
+struct PackedJson {
+    frames: Frames,
+    meta: Meta,
+    #[serde(default)]
+    animations: HashMap<String, Vec<String>>, // TexturePacker, clip name to frame names
+}
+
+
+/// A packed atlas ready for `TextureAtlasLayout`. Every clip's frames are laid out one
+/// after the other, so a clip is always a `first..=last` run even when the export shares frames
+pub struct PackedAtlas {
+    pub size: Vec2,
+    pub frames: Vec<Rect>,
+    pub clips: HashMap<String, Indices>,
+}
+
+impl PackedAtlas {
+    /// `path` is relative to the assets folder, like the image next to it
+    pub fn load(path: &str) -> PackedAtlas {
+        let file = File::open(format!("assets/{path}")).unwrap();
+        let reader = BufReader::new(file);
+        let data: PackedJson = serde_json::from_reader(reader).unwrap();
+        PackedAtlas::from(data)
+    }
+
+    fn from(data: PackedJson) -> PackedAtlas {
+        // "Hash" exports lose their order in a map, frame numbers put it back
+        let frames: Vec<(String, PackedFrame)> = match data.frames {
+            Frames::Array(frames) => frames.into_iter().map(|frame| (frame.filename.clone(), frame)).collect(),
+            Frames::Hash(frames) => {
+                let mut frames: Vec<(String, PackedFrame)> = frames.into_iter().collect();
+                frames.sort_by_key(|(name, _)| (natural_key(name), name.clone()));
+                frames
+            }
+        };
+
+        let mut runs: Vec<(String, Vec<usize>, LoopMode)> = Vec::new();
+        if !data.meta.frame_tags.is_empty() {
+            for tag in data.meta.frame_tags {
+                let mut order: Vec<usize> = (tag.from..=tag.to.min(frames.len().saturating_sub(1))).collect();
+                if tag.direction == "reverse" { order.reverse(); }
+                let mode = if tag.direction == "pingpong" { LoopMode::PingPong } else { LoopMode::Loop };
+                runs.push((tag.name, order, mode));
+            }
+        } else if !data.animations.is_empty() {
+            for (name, names) in data.animations {
+                let order = names.iter()
+                    .filter_map(|frame_name| frames.iter().position(|(n, _)| n == frame_name))
+                    .collect();
+                runs.push((name, order, LoopMode::Loop));
+            }
+        } else {
+            for (i, (name, _)) in frames.iter().enumerate() {
+                let clip = natural_key(name).0;
+                match runs.iter_mut().find(|(existing, _, _)| *existing == clip) {
+                    Some((_, order, _)) => order.push(i),
+                    None => runs.push((clip, vec![i], LoopMode::Loop)),
+                }
+            }
+        }
+
+        let mut atlas = PackedAtlas {
+            size: Vec2::new(data.meta.size.w, data.meta.size.h),
+            frames: Vec::new(),
+            clips: HashMap::new(),
+        };
+        for (name, order, mode) in runs {
+            if order.is_empty() { continue; }
+            let first = atlas.frames.len();
+            let mut durations = Vec::new();
+            for i in order {
+                let frame = &frames[i].1;
+                atlas.frames.push(Rect::new(frame.frame.x, frame.frame.y, frame.frame.x + frame.frame.w, frame.frame.y + frame.frame.h));
+                durations.extend(frame.duration.map(|ms| ms / 1000.0));
+            }
+            let last = atlas.frames.len() - 1;
+            // only keep the timings if every frame had one
+            if durations.len() != last - first + 1 { durations.clear(); }
+
+            atlas.clips.insert(name, Indices {
+                first,
+                last,
+                duration_s: None,
+                frame_durations_s: durations,
+                mode,
+                events: Vec::new(),
+            });
+        }
+        atlas
+    }
+}
+
+
+/// splits `walk_12.png` into ("walk", 12) so frames sort 1, 2, 10 and not 1, 10, 2
+fn natural_key(name: &str) -> (String, u32) {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (prefix, number) = stem.split_at(stem.len() - digits);
+    let prefix = prefix.trim_end_matches(['_', '-', ' ']);
+    (prefix.to_string(), number.parse().unwrap_or(0))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // x of every frame rect a clip plays, in play order
+    fn clip_xs(atlas: &PackedAtlas, clip: &str) -> Vec<f32> {
+        let indices = &atlas.clips[clip];
+        atlas.frames[indices.first..=indices.last].iter().map(|rect| rect.min.x).collect()
+    }
+
+    #[test]
+    fn natural_key_sorts_numbers_by_value() {
+        assert_eq!(natural_key("walk_12.png"), ("walk".to_string(), 12));
+        assert_eq!(natural_key("frame10"), ("frame".to_string(), 10));
+        assert!(natural_key("frame2.png") < natural_key("frame10.png"));
+        assert!("frame10.png" < "frame2.png"); // what a plain string sort would do
+    }
+
+    #[test]
+    fn aseprite_hash_frames_come_back_in_frame_order() {
+        let atlas = PackedAtlas::load("atlases/aseprite_sample.json");
+        assert_eq!(atlas.size, Vec2::new(192.0, 24.0));
+        assert_eq!(atlas.frames.len(), 12);
+        // frameN sits at x = 16 * N, so frame10 and frame11 must not land after frame1
+        assert_eq!(clip_xs(&atlas, "idle"), vec![0.0, 16.0, 32.0, 48.0]);
+        assert_eq!(clip_xs(&atlas, "walk"), vec![64.0, 80.0, 96.0, 112.0, 128.0, 144.0]);
+        assert_eq!(atlas.frames[0], Rect::new(0.0, 0.0, 16.0, 24.0));
+    }
+
+    #[test]
+    fn aseprite_tags_map_to_clip_ranges() {
+        let atlas = PackedAtlas::load("atlases/aseprite_sample.json");
+        let idle = &atlas.clips["idle"];
+        assert_eq!((idle.first, idle.last), (0, 3));
+        assert!(idle.mode == LoopMode::Loop);
+        assert_eq!(idle.frame_durations_s, vec![0.1; 4]);
+
+        let walk = &atlas.clips["walk"];
+        assert_eq!((walk.first, walk.last), (4, 9));
+        assert!(walk.mode == LoopMode::PingPong);
+
+        // reverse tags are laid out backwards so the clip still plays first..=last
+        let attack = &atlas.clips["attack"];
+        assert_eq!((attack.first, attack.last), (10, 11));
+        assert_eq!(clip_xs(&atlas, "attack"), vec![176.0, 160.0]);
+        assert_eq!(attack.frame_durations_s, vec![0.05; 2]);
+    }
+
+    #[test]
+    fn texturepacker_animations_map_to_clip_ranges() {
+        let atlas = PackedAtlas::load("atlases/texturepacker_sample.json");
+        assert_eq!(atlas.clips.len(), 2);
+        assert_eq!(atlas.frames.len(), 12);
+
+        let run = &atlas.clips["run"];
+        assert_eq!(run.last - run.first + 1, 10);
+        assert!(run.mode == LoopMode::Loop);
+        assert!(run.frame_durations_s.is_empty()); // no per frame timings in TexturePacker
+        let rects = &atlas.frames[run.first..=run.last];
+        assert_eq!(rects[0], Rect::new(0.0, 0.0, 20.0, 30.0));
+        assert_eq!(rects[9], Rect::new(60.0, 30.0, 80.0, 60.0)); // run_10, second row
+
+        // the animation's own order wins over the frame list
+        let jump = &atlas.clips["jump"];
+        assert_eq!(jump.last - jump.first + 1, 2);
+        assert_eq!(atlas.frames[jump.first], Rect::new(100.0, 30.0, 120.0, 60.0)); // jump_2
+        assert_eq!(atlas.frames[jump.last], Rect::new(80.0, 30.0, 100.0, 60.0)); // jump_1
+    }
+}
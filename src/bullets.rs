@@ -1,12 +1,250 @@
-use crate::animations::{TopDownStates, AnimationState};
+use crate::anim_graph::AnimationParams;
 use crate::enemies::Enemy;
 use crate::player::Player;
-use crate::health::Health;
+use crate::health::{Health, Invulnerable};
 use crate::gamedata::*;
-use crate::guns::Gun;
+use crate::guns::{Gun, Guns};
+use crate::atlas::PackedAtlas;
+use crate::effects::Impact;
+use crate::level::Level;
+use crate::vfx::{Vfx, VfxKind};
+use crate::damage_numbers::Damage;
 
 use bevy::prelude::*;
-use bevy::sprite::MaterialMesh2dBundle;
+use bevy::render::mesh::{Indices as MeshIndices, PrimitiveTopology};
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_asset::{RenderAssetUsages, RenderAssets};
+use bevy::render::render_resource::{
+    AsBindGroup, AsBindGroupShaderType, BlendComponent, BlendFactor, BlendOperation, BlendState,
+    RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+};
+use bevy::sprite::{
+    ColorMaterialFlags, ColorMaterialUniform, Material2d, Material2dKey, MaterialMesh2dBundle,
+    COLOR_MATERIAL_SHADER_HANDLE,
+};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::BufReader;
+
+// =======
+// STRUCTS
+// =======
+
+/// all point along +y, which is the way bullets fly
+#[derive(Deserialize, Clone, Copy)]
+pub enum BulletShape {
+    Circle, Ellipse, Rice, Arrow, Star
+}
+
+impl BulletShape {
+    /// unit sized, the bullet's transform scales it to the visual size
+    fn mesh(self) -> Mesh {
+        match self {
+            BulletShape::Circle => Circle::new(1.0).into(),
+            BulletShape::Ellipse => Ellipse::new(0.7, 1.0).into(),
+            BulletShape::Rice => Ellipse::new(0.4, 1.0).into(),
+            BulletShape::Arrow => Triangle2d::new(Vec2::new(0.0, 1.0), Vec2::new(-0.6, -0.8), Vec2::new(0.6, -0.8)).into(),
+            BulletShape::Star => star_mesh(5, 0.45),
+        }
+    }
+}
+
+fn star_mesh(points: u32, inner: f32) -> Mesh {
+    let corners = points * 2;
+    let mut positions = vec![[0.0, 0.0, 0.0]];
+    for i in 0..corners {
+        let radius = if i % 2 == 0 { 1.0 } else { inner };
+        let dir = Vec2::from_angle(std::f32::consts::FRAC_PI_2 + std::f32::consts::TAU * i as f32 / corners as f32);
+        positions.push([dir.x * radius, dir.y * radius, 0.0]);
+    }
+    let uvs: Vec<[f32; 2]> = positions.iter().map(|[x, y, _]| [x * 0.5 + 0.5, 0.5 - y * 0.5]).collect();
+    let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+    let indices = (0..corners).flat_map(|i| [0, i + 1, (i + 1) % corners + 1]).collect();
+
+    Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(MeshIndices::U32(indices))
+}
+
+/// unit sized like the shapes along its longer side, showing the `uv` part of a texture
+fn frame_mesh(uv: Rect, aspect: Vec2) -> Mesh {
+    let half = aspect / aspect.max_element();
+    let positions = vec![[-half.x, -half.y, 0.0], [half.x, -half.y, 0.0], [half.x, half.y, 0.0], [-half.x, half.y, 0.0]];
+    let uvs = vec![[uv.min.x, uv.max.y], [uv.max.x, uv.max.y], [uv.max.x, uv.min.y], [uv.min.x, uv.min.y]];
+    let normals = vec![[0.0, 0.0, 1.0]; 4];
+
+    Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(MeshIndices::U32(vec![0, 1, 2, 0, 2, 3]))
+}
+
+/// one frame out of a packed atlas, drawn instead of a shape
+#[derive(Deserialize)]
+pub struct BulletSprite {
+    pub path: String,  // the image
+    pub atlas: String, // its Aseprite or TexturePacker json, see atlas.rs
+    pub clip: String,  // a tag, an animation, or a frame name without its number
+    #[serde(default)]
+    pub index: usize,  // which frame of the clip
+}
+
+impl BulletSprite {
+    fn mesh(&self) -> Mesh {
+        let atlas = PackedAtlas::load(&self.atlas);
+        let clip = &atlas.clips[&self.clip];
+        let frame = atlas.frames[(clip.first + self.index).min(clip.last)];
+        frame_mesh(Rect::from_corners(frame.min / atlas.size, frame.max / atlas.size), frame.size())
+    }
+}
+
+/// `ColorMaterial` with a switch for additive blending, where overlapping bullets add up
+/// into brighter spots. Same bindings as `ColorMaterial` so it borrows its shader
+#[derive(Asset, AsBindGroup, TypePath, Clone)]
+#[uniform(0, ColorMaterialUniform)]
+#[bind_group_data(BulletMaterialKey)]
+pub struct BulletMaterial {
+    pub color: Color,
+    #[texture(1)]
+    #[sampler(2)]
+    pub texture: Option<Handle<Image>>,
+    pub additive: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BulletMaterialKey {
+    additive: bool,
+}
+
+impl From<&BulletMaterial> for BulletMaterialKey {
+    fn from(material: &BulletMaterial) -> Self {
+        BulletMaterialKey { additive: material.additive }
+    }
+}
+
+impl AsBindGroupShaderType<ColorMaterialUniform> for BulletMaterial {
+    fn as_bind_group_shader_type(&self, _images: &RenderAssets<Image>) -> ColorMaterialUniform {
+        let flags = if self.texture.is_some() { ColorMaterialFlags::TEXTURE } else { ColorMaterialFlags::NONE };
+        ColorMaterialUniform {
+            color: self.color.as_linear_rgba_f32().into(),
+            flags: flags.bits(),
+        }
+    }
+}
+
+impl Material2d for BulletMaterial {
+    fn fragment_shader() -> ShaderRef {
+        COLOR_MATERIAL_SHADER_HANDLE.into()
+    }
+
+    fn specialize(
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        key: Material2dKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        if !key.bind_group_data.additive { return Ok(()); }
+        // the color is scaled by its alpha and added on top, the alpha channel blends as usual
+        let target = descriptor.fragment.as_mut().and_then(|fragment| fragment.targets[0].as_mut());
+        if let Some(target) = target {
+            target.blend = Some(BlendState {
+                color: BlendComponent {
+                    src_factor: BlendFactor::SrcAlpha,
+                    dst_factor: BlendFactor::One,
+                    operation: BlendOperation::Add,
+                },
+                alpha: BlendComponent::OVER,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TrailConfig {
+    pub length: usize, // how many past positions it remembers
+    pub alpha: f32,    // at the bullet end, fades to nothing at the tail
+}
+
+#[derive(Deserialize)]
+pub struct BulletStyleConfig {
+    #[serde(default = "BulletStyleConfig::default_shape")]
+    pub shape: BulletShape,
+    #[serde(default)]
+    pub sprite: Option<BulletSprite>,
+    // how big it looks compared to its hitbox, shmup bullets usually hit smaller than they look
+    #[serde(default = "BulletStyleConfig::default_visual_scale")]
+    pub visual_scale: f32,
+    // multiplies the gun's color, past 1 the bloom makes it glow
+    #[serde(default = "BulletStyleConfig::default_brightness")]
+    pub brightness: f32,
+    // overlapping bullets add up into brighter spots instead of covering each other
+    #[serde(default)]
+    pub additive: bool,
+    #[serde(default)]
+    pub trail: Option<TrailConfig>,
+}
+
+impl BulletStyleConfig {
+    fn default_shape() -> BulletShape { BulletShape::Circle }
+    fn default_visual_scale() -> f32 { 1.0 }
+    fn default_brightness() -> f32 { 1.0 }
+}
+
+struct BulletStyle {
+    mesh: Handle<Mesh>,
+    texture: Option<Handle<Image>>, // sprites are a quad with their frame of the texture
+    visual_scale: f32,
+    brightness: f32,
+    additive: bool,
+    trail: Option<(usize, f32)>,
+}
+
+/// Every style from config/bullet_styles.json with its mesh and texture already loaded,
+/// so firing only clones handles
+#[derive(Resource)]
+pub struct BulletStyles {
+    styles: HashMap<String, BulletStyle>,
+    plain: BulletStyle, // guns without a style, or with one that doesn't exist
+}
+
+impl FromWorld for BulletStyles {
+    fn from_world(world: &mut World) -> Self {
+        let file = File::open("config/bullet_styles.json").unwrap();
+        let reader = BufReader::new(file);
+        let configs: HashMap<String, BulletStyleConfig> = serde_json::from_reader(reader).unwrap();
+
+        let mut prepare = |config: BulletStyleConfig| {
+            let (mesh, texture) = match config.sprite {
+                Some(sprite) => (sprite.mesh(), Some(world.resource::<AssetServer>().load(sprite.path))),
+                None => (config.shape.mesh(), None),
+            };
+            BulletStyle {
+                mesh: world.resource_mut::<Assets<Mesh>>().add(mesh),
+                texture,
+                visual_scale: config.visual_scale,
+                brightness: config.brightness,
+                additive: config.additive,
+                trail: config.trail.map(|trail| (trail.length.max(2), trail.alpha)),
+            }
+        };
+
+        let plain = prepare(BulletStyleConfig {
+            shape: BulletShape::Circle, sprite: None, visual_scale: 1.0, brightness: 1.0, additive: false, trail: None
+        });
+        let styles = configs.into_iter().map(|(name, config)| (name, prepare(config))).collect();
+        BulletStyles { styles, plain }
+    }
+}
+
+impl BulletStyles {
+    fn get(&self, name: Option<&str>) -> &BulletStyle {
+        name.and_then(|name| self.styles.get(name)).unwrap_or(&self.plain)
+    }
+}
 
 // ==========
 // COMPONENTS
@@ -18,63 +256,89 @@ pub struct Bullet {
     pub size: f32,
     pub damage: i32,
     pub target: EntityType,
+    pub shooter: Entity,
+}
+
+/// A bullet that was just fired and is still growing in. It doesn't move or hit anything
+/// until the timer runs out, so dense patterns show up before they can hurt
+#[derive(Component)]
+pub struct Arming {
+    pub timer: Timer,
+    pub scale: f32, // what it grows to
+}
+
+/// where the bullet has been lately, oldest first
+#[derive(Component)]
+pub struct Trail {
+    pub points: VecDeque<Vec2>,
+    pub length: usize,
+    pub alpha: f32,
+    pub color: Color,
 }
 
 
 impl Bullet {
-    pub fn from(gun: &Gun) -> Self {
+    pub fn from(gun: &Gun, shooter: Entity) -> Self {
         Self {
             vel: gun.bullet_vel,
             size: gun.bullet_size,
             damage: gun.bullet_damage,
-            target: gun.target
+            target: gun.target,
+            shooter,
         }
     }
 
+    /// fans `g.bullet_count` bullets evenly across `g.spread` around the gun's rotation
     #[inline] // means this is used in system function
     pub fn spawn(
         g: &Gun, 
+        shooter: Entity,
         t: &Transform, 
+        styles: &BulletStyles,
         commands: &mut Commands, 
-        meshes: &mut ResMut<Assets<Mesh>>, 
-        materials: &mut ResMut<Assets<ColorMaterial>>
+        materials: &mut ResMut<Assets<BulletMaterial>>
     ) {
-        commands.spawn((
-            Bullet::from(g),
-            MaterialMesh2dBundle {
-                mesh: meshes.add(Circle::new(g.bullet_size)).into(),
-                material: materials.add(ColorMaterial::from(g.color)),
-                transform: Transform {
-                    translation: t.translation,
-                    rotation: g.rotation,
-                    ..default()
-                },
-                ..default()
-            }
-        ));
-    }
+        let style = styles.get(g.style.as_deref());
+        let [r, gr, b, a] = g.color.as_rgba_f32();
+        let color = Color::rgba(r * style.brightness, gr * style.brightness, b * style.brightness, a);
+        // one per volley, not per bullet
+        let material = materials.add(BulletMaterial { color, texture: style.texture.clone(), additive: style.additive });
 
-    #[inline]
-    pub fn spawn_straight(
-        g: &Gun, 
-        t: &Transform, 
-        commands: &mut Commands, 
-        meshes: &mut ResMut<Assets<Mesh>>, 
-        materials: &mut ResMut<Assets<ColorMaterial>>
-    ) {
-        commands.spawn((
-            Bullet::from(g),
-            MaterialMesh2dBundle {
-                mesh: meshes.add(Circle::new(g.bullet_size)).into(),
-                material: materials.add(ColorMaterial::from(g.color)),
-                transform: Transform {
-                    translation: t.translation,
-                    rotation: t.rotation, // only differences, causes bullet to shoot straight from entity tranform
+        let translation = t.translation + t.rotation.mul_vec3(g.offset.extend(0.0));
+        let full_circle = g.spread >= std::f32::consts::TAU - f32::EPSILON;
+        let step = match g.bullet_count {
+            1 => 0.0,
+            n if full_circle => g.spread / n as f32, // first and last would overlap on a ring
+            n => g.spread / (n - 1) as f32,
+        };
+        let start = if full_circle { 0.0 } else { -g.spread / 2.0 };
+
+        for i in 0..g.bullet_count {
+            let angle = if g.bullet_count == 1 { 0.0 } else { start + step * i as f32 };
+            let scale = g.bullet_size * style.visual_scale;
+            let arming = !g.spawn_delay.is_zero();
+            let transform = Transform {
+                translation,
+                rotation: g.rotation * Quat::from_rotation_z(angle),
+                scale: Vec3::splat(if arming { 0.0 } else { scale }),
+            };
+
+            let mut bullet = commands.spawn((
+                Bullet::from(g, shooter),
+                MaterialMesh2dBundle {
+                    mesh: style.mesh.clone().into(),
+                    material: material.clone(),
+                    transform,
                     ..default()
-                },
-                ..default()
+                }
+            ));
+            if arming {
+                bullet.insert(Arming { timer: Timer::new(g.spawn_delay, TimerMode::Once), scale });
+            }
+            if let Some((length, alpha)) = style.trail {
+                bullet.insert(Trail { points: VecDeque::with_capacity(length), length, alpha, color });
             }
-        ));
+        }
     }
 }
 
@@ -83,21 +347,60 @@ impl Bullet {
 // SYSTEMS
 // =======
 
+/// remembers where each bullet was this frame and draws the fading line behind it
+pub fn draw_trails(
+    mut gizmos: Gizmos,
+    mut trails: Query<(&Transform, &mut Trail), With<Bullet>>,
+) {
+    for (bt, mut trail) in &mut trails {
+        if trail.points.len() == trail.length { trail.points.pop_front(); }
+        trail.points.push_back(bt.translation.truncate());
+        if trail.points.len() < 2 { continue; }
+
+        let n = trail.points.len() as f32;
+        let (alpha, color) = (trail.alpha, trail.color);
+        gizmos.linestrip_gradient_2d(trail.points.iter().enumerate().map(|(i, point)| {
+            (*point, color.with_a(alpha * (i as f32 + 1.0) / n))
+        }));
+    }
+}
+
+
 // SIMPLIFY BULLETS QUERY
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn handle(
     t: Res<Time>,
     data: Res<GameData>,
+    level: Res<Level>,
     mut commands: Commands,
+    mut impacts: EventWriter<Impact>,
+    mut vfx: EventWriter<Vfx>,
+    mut damage: EventWriter<Damage>,
     mut query: ParamSet<(
-        Query<(&Transform, &mut Health, &mut AnimationState), With<Player>>, 
-        Query<(&Transform, &mut Health, &mut AnimationState), With<Enemy>>
+        Query<(Entity, &Transform, &mut Health, &mut AnimationParams, Has<Invulnerable>), With<Player>>, 
+        Query<(Entity, &Transform, &mut Health, &mut AnimationParams), With<Enemy>>
     )>,
     mut bullets: Query<
-        (Entity, &mut Transform, &Bullet), 
+        (Entity, &mut Transform, &Bullet, Option<&mut Arming>), 
         (Without<Player>, Without<Enemy>, With<Bullet>)
-    >
+    >,
+    shooters: Query<(), With<Guns>>,
 ) {
-    for (bullet_entity, mut bt, bullet) in &mut bullets {
+    for (bullet_entity, mut bt, bullet, arming) in &mut bullets {
+        // still growing in, harmless and in place
+        if let Some(mut arming) = arming {
+            // whoever fired it is gone or dying, it never gets to fly
+            if !shooters.contains(bullet.shooter) {
+                commands.entity(bullet_entity).despawn();
+                vfx.send(Vfx { kind: VfxKind::BulletCancel, pos: bt.translation.truncate(), target: None });
+                continue;
+            }
+            arming.timer.tick(t.delta());
+            bt.scale = Vec3::splat(arming.scale * arming.timer.fraction());
+            if arming.timer.finished() { commands.entity(bullet_entity).remove::<Arming>(); }
+            continue;
+        }
+
         // update
         let base_direction = Vec3::new(0.0, 1.0, 0.0); 
         let rotated_direction = bt.rotation.mul_vec3(base_direction);
@@ -105,35 +408,38 @@ pub fn handle(
         bt.translation.x += displacement.x;
         bt.translation.y += displacement.y;
 
-        // drop when outside_bounds
-        let outside_width_bounds = bt.translation.x >= data.width || bt.translation.x <= -data.width;
-        let outside_height_bounds = bt.translation.y >= data.height || bt.translation.y <= -data.height;
-
-        if outside_width_bounds || outside_height_bounds {
+        // drop once well past the arena's edge
+        if !level.arena.contains(bt.translation.truncate(), level.cull_margin) {
             commands.entity(bullet_entity).despawn();
         }
 
         // collision detection
         match bullet.target {
             EntityType::Player => {
-                if let Ok((transform, mut health, mut state)) = query.p0().get_single_mut() {
+                if let Ok((entity, transform, mut health, mut params, invulnerable)) = query.p0().get_single_mut() {
+                    if invulnerable { continue; } // passes right through
                     let distance = bt.translation.distance(transform.translation);
                     // in here, the size of the player and boss should be obtained from their
                     // transforms fuck, not the 'gamedata object' 
-                    if distance <= bullet.size + transform.scale.x as f32 { // change to HitboxSize, or transform.scale waterfall from the 3d / 2d animations
+                    if distance <= bullet.size + transform.scale.x { // change to HitboxSize, or transform.scale waterfall from the 3d / 2d animations
                         health.0 -= bullet.damage;
-                        state.change_if_its_not(TopDownStates::Hurt);
+                        params.trigger("hurt");
                         commands.entity(bullet_entity).despawn();
+                        impacts.send(Impact::PlayerHit { damage: bullet.damage });
+                        vfx.send(Vfx { kind: VfxKind::PlayerHit, pos: bt.translation.truncate(), target: Some(entity) });
+                        damage.send(Damage { amount: bullet.damage, pos: bt.translation.truncate(), target: entity, to: EntityType::Player });
                     }
                 }
             },
             EntityType::Enemy => {
-                for (transform, mut health, mut state) in &mut query.p1() {
+                for (entity, transform, mut health, mut params) in &mut query.p1() {
                     let distance = bt.translation.distance(transform.translation);
                     if distance <= bullet.size + transform.scale.x * data.scaling as f32 {
                         health.0 -= bullet.damage;
-                        state.change_if_its_not(TopDownStates::Hurt);
+                        params.trigger("hurt");
                         commands.entity(bullet_entity).despawn();
+                        vfx.send(Vfx { kind: VfxKind::BulletImpact, pos: bt.translation.truncate(), target: Some(entity) });
+                        damage.send(Damage { amount: bullet.damage, pos: bt.translation.truncate(), target: entity, to: EntityType::Enemy });
                     }
                 }
             }
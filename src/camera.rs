@@ -1,20 +1,89 @@
 use bevy::{
     core_pipeline::{ bloom::BloomSettings, core_2d::Camera2dBundle, tonemapping::Tonemapping }, 
-    ecs::{ query::{ With, Without }, system::{ Commands, Query }, component::Component, }, 
-    render::camera::Camera, 
-    transform::components::Transform, 
+    ecs::{ query::{ With, Without }, system::{ Commands, Query, Res, ResMut, Resource }, component::Component, }, 
+    math::{ Quat, Vec2 },
+    render::camera::{ Camera, OrthographicProjection, ScalingMode }, 
+    time::Time,
+    transform::components::{ GlobalTransform, Transform }, 
+    window::Window,
     utils::default
 };
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
 use crate::player::Player;
+use crate::gamedata::{GameData, ControlScheme};
+use crate::input::{Action, ActionState};
+use crate::effects::CameraEffects;
+use crate::level::Level;
 
+// =======
+// STRUCTS
+// =======
+
+/// Where the camera looks. Part of the simulation since it decides which way is up for
+/// the screen-relative controls, so it only changes on a fixed tick
+#[derive(Resource, Copy, Clone, PartialEq, Deserialize)]
+pub enum CameraMode {
+    Fixed,          // frames the whole arena, never moves
+    Follow,         // trails the player, and turns with them in the tank scheme
+    FollowNoRotate, // trails the player, up is always up
+}
+
+impl CameraMode {
+    fn next(self) -> CameraMode {
+        match self {
+            CameraMode::Fixed => CameraMode::Follow,
+            CameraMode::Follow => CameraMode::FollowNoRotate,
+            CameraMode::FollowNoRotate => CameraMode::Fixed,
+        }
+    }
+}
+
+#[derive(Resource, Deserialize)]
+pub struct CameraConfig {
+    pub mode: CameraMode,           // what the camera starts in
+    pub smoothing: f32,             // how fast the camera catches up, per second. 0 snaps to the player
+    pub deadzone: f32,              // the player can move this far from the center before the camera follows
+    pub look_ahead: f32,            // how far ahead of the player's movement the camera looks
+    pub look_ahead_smoothing: f32,  // how fast the look-ahead swings around when the player turns
+    pub clamp_to_arena: bool,       // never show anything past the arena's edges
+}
+
+impl Default for CameraConfig {
+    fn default() -> CameraConfig {
+        let file = File::open("config/camera.json").unwrap();
+        let reader = BufReader::new(file);
+        let data: CameraConfig = serde_json::from_reader(reader).unwrap();
+        data
+    }
+}
+
+// ==========
+// COMPONENTS
+// ==========
 
 #[derive(Component)]
 pub struct GameCamera;
 
+/// what the follow modes remember between frames
+#[derive(Component, Default)]
+pub struct CameraRig {
+    center: Vec2, // where the camera rests, before effects shake it
+    last_player: Option<Vec2>,
+    look_ahead: Vec2,
+    framing: Option<CameraMode>, // the mode the projection was last set up for
+}
+
+// =======
+// SYSTEMS
+// =======
 
 pub fn spawn(mut commands: Commands) {
     commands.spawn((
         GameCamera,
+        CameraRig::default(),
+        CameraEffects::default(),
         Camera2dBundle {
             camera: Camera {
                 hdr: true, // 1. HDR is required for bloom
@@ -28,12 +97,99 @@ pub fn spawn(mut commands: Commands) {
 }
 
 
+/// where the mouse cursor is in world space, if it's inside the window
+pub fn cursor_world_position(window: &Window, camera: &Camera, ct: &GlobalTransform) -> Option<Vec2> {
+    window.cursor_position().and_then(|cursor| camera.viewport_to_world_2d(ct, cursor))
+}
+
+
+/// Which way is up on screen. Worked out from the config and the player instead of read
+/// off the camera, so the simulation never depends on what the camera happens to be doing
+pub fn screen_rotation(gamedata: &GameData, mode: CameraMode, pt: &Transform) -> Quat {
+    // only the tank scheme spins the world, the others need the screen axes to stay put.
+    // Spinning under mouse aim would drag the cursor around with the player forever
+    if mode == CameraMode::Follow && gamedata.control_scheme == ControlScheme::Tank && !gamedata.mouse_aim {
+        pt.rotation
+    } else {
+        Quat::IDENTITY
+    }
+}
+
+
+pub fn cycle_mode(actions: Res<ActionState>, mut mode: ResMut<CameraMode>) {
+    if actions.just_pressed(Action::CycleCamera) {
+        *mode = mode.next();
+    }
+}
+
+
+#[allow(clippy::type_complexity)]
 pub fn follow_player(
+    time: Res<Time>,
+    gamedata: Res<GameData>,
+    level: Res<Level>,
+    config: Res<CameraConfig>,
+    mode: Res<CameraMode>,
     player_t: Query<&Transform, With<Player>>,
-    mut camera_t: Query<&mut Transform, (Without<Player>, With<Camera>)>,
+    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection, &mut CameraRig), (Without<Player>, With<GameCamera>)>,
 ) {
-    if let (Ok(pt), Ok(mut ct)) = (player_t.get_single(), camera_t.get_single_mut()) {
-        ct.rotation = pt.rotation;
-        ct.translation = pt.translation;         
+    let Ok((mut ct, mut projection, mut rig)) = camera_q.get_single_mut() else { return; };
+    let dt = time.delta_seconds();
+
+    let half_arena = level.arena.half_extents();
+    if rig.framing != Some(*mode) {
+        projection.scaling_mode = match *mode {
+            CameraMode::Fixed => ScalingMode::AutoMin { min_width: half_arena.x * 2.0, min_height: half_arena.y * 2.0 },
+            CameraMode::Follow | CameraMode::FollowNoRotate => ScalingMode::WindowSize(1.0),
+        };
+        rig.framing = Some(*mode);
     }
+
+    let Ok(pt) = player_t.get_single() else { return; };
+    let player = pt.translation.truncate();
+    ct.rotation = screen_rotation(&gamedata, *mode, pt);
+
+    if *mode == CameraMode::Fixed {
+        rig.center = Vec2::ZERO;
+        ct.translation.x = 0.0;
+        ct.translation.y = 0.0;
+        rig.last_player = Some(player);
+        return;
+    }
+
+    // look where the player is heading, eased so it doesn't flip around with every tap
+    let velocity = match rig.last_player {
+        Some(last) if dt > 0.0 => (player - last) / dt,
+        _ => Vec2::ZERO,
+    };
+    rig.last_player = Some(player);
+    let wanted_look_ahead = velocity.normalize_or_zero() * config.look_ahead;
+    rig.look_ahead = rig.look_ahead.lerp(wanted_look_ahead, 1.0 - (-config.look_ahead_smoothing * dt).exp());
+
+    // only chase the part of the offset that's outside the deadzone
+    let center = rig.center;
+    let target = player + rig.look_ahead;
+    let offset = target - center;
+    let desired = if offset.length() > config.deadzone {
+        target - offset.normalize() * config.deadzone
+    } else {
+        center
+    };
+
+    let mut next = if config.smoothing <= 0.0 {
+        desired
+    } else {
+        center.lerp(desired, 1.0 - (-config.smoothing * dt).exp())
+    };
+
+    if config.clamp_to_arena {
+        // a view bigger than the arena just centers on it
+        let half_view = projection.area.half_size();
+        let room = (half_arena - half_view).max(Vec2::ZERO);
+        next = next.clamp(-room, room);
+    }
+
+    rig.center = next;
+    ct.translation.x = next.x;
+    ct.translation.y = next.y;
 }
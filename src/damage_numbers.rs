@@ -0,0 +1,159 @@
+use crate::camera::{CameraMode, screen_rotation};
+use crate::gamedata::{EntityType, GameData};
+use crate::player::Player;
+use bevy::prelude::*;
+use serde::Deserialize;
+use rand::Rng;
+use std::fs::File;
+use std::io::BufReader;
+
+// Visual only like vfx.rs, the numbers live on the frame and never touch the simulation
+
+// =======
+// STRUCTS
+// =======
+
+/// `target` took `amount` at `pos`, `to` says whose side got hurt
+#[derive(Event, Copy, Clone)]
+pub struct Damage {
+    pub amount: i32,
+    pub pos: Vec2,
+    pub target: Entity,
+    pub to: EntityType,
+}
+
+#[derive(Resource, Deserialize)]
+pub struct DamageNumbersConfig {
+    pub enabled: bool,
+    pub font: String,
+    pub font_size: f32,
+    pub lifetime_s: f32,
+    pub rise_speed: f32,
+    pub drift: f32,       // random sideways speed, either way
+    pub coalesce_s: f32,  // hits on the same target closer than this add up into one number
+    pub grow_per_hit: f32,
+    pub max_scale: f32,
+    // damage the player takes
+    pub player_r: f32,
+    pub player_g: f32,
+    pub player_b: f32,
+    // damage the player deals
+    pub enemy_r: f32,
+    pub enemy_g: f32,
+    pub enemy_b: f32,
+}
+
+impl Default for DamageNumbersConfig {
+    fn default() -> DamageNumbersConfig {
+        let file = File::open("config/damage_numbers.json").unwrap();
+        let reader = BufReader::new(file);
+        let data: DamageNumbersConfig = serde_json::from_reader(reader).unwrap();
+        data
+    }
+}
+
+impl DamageNumbersConfig {
+    fn color(&self, to: EntityType) -> Color {
+        match to {
+            EntityType::Player => Color::rgb(self.player_r, self.player_g, self.player_b),
+            EntityType::Enemy => Color::rgb(self.enemy_r, self.enemy_g, self.enemy_b),
+        }
+    }
+}
+
+// ==========
+// COMPONENTS
+// ==========
+
+#[derive(Component)]
+pub struct DamageNumber {
+    pub target: Entity,
+    pub total: i32,
+    pub vel: Vec2,
+    pub since_hit: f32,
+    pub timer: Timer,
+}
+
+// =======
+// SYSTEMS
+// =======
+
+pub fn spawn(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<DamageNumbersConfig>,
+    mut events: EventReader<Damage>,
+    mut numbers: Query<(&mut DamageNumber, &mut Text, &mut Transform)>,
+) {
+    if !config.enabled { events.clear(); return; }
+    let mut rng = rand::thread_rng();
+
+    // a frame can hold several simulation ticks and a volley lands many hits at once, add them
+    // up per target first. A number spawned below only exists once the commands run
+    let mut hits: Vec<(Damage, u32)> = Vec::new();
+    for event in events.read() {
+        match hits.iter_mut().find(|(hit, _)| hit.target == event.target) {
+            Some((hit, count)) => { hit.amount += event.amount; *count += 1; },
+            None => hits.push((*event, 1)),
+        }
+    }
+
+    for (hit, count) in hits {
+        let grow = config.grow_per_hit * count as f32;
+
+        // keep counting up on a number that's still fresh instead of stacking a new one
+        let fresh = numbers.iter_mut().find(|(number, ..)| number.target == hit.target && number.since_hit <= config.coalesce_s);
+        if let Some((mut number, mut text, mut t)) = fresh {
+            number.total += hit.amount;
+            number.since_hit = 0.0; // the lifetime keeps running so a steady stream still rolls over
+            text.sections[0].value = number.total.to_string();
+            t.scale = Vec3::splat((t.scale.x + grow).min(config.max_scale));
+            continue;
+        }
+
+        commands.spawn((
+            DamageNumber {
+                target: hit.target,
+                total: hit.amount,
+                vel: Vec2::new(rng.gen_range(-config.drift..=config.drift), config.rise_speed),
+                since_hit: 0.0,
+                timer: Timer::from_seconds(config.lifetime_s, TimerMode::Once),
+            },
+            Text2dBundle {
+                text: Text::from_section(hit.amount.to_string(), TextStyle {
+                    font: asset_server.load(config.font.clone()),
+                    font_size: config.font_size,
+                    color: config.color(hit.to),
+                }),
+                // the first hit makes the number, every other one grows it
+                transform: Transform::from_translation(hit.pos.extend(10.0))
+                    .with_scale(Vec3::splat((1.0 + grow - config.grow_per_hit).min(config.max_scale))),
+                ..default()
+            },
+        ));
+    }
+}
+
+pub fn update(
+    time: Res<Time>,
+    gamedata: Res<GameData>,
+    camera_mode: Res<CameraMode>,
+    mut commands: Commands,
+    player_q: Query<&Transform, With<Player>>,
+    mut numbers: Query<(Entity, &mut Transform, &mut Text, &mut DamageNumber), Without<Player>>,
+) {
+    let dt = time.delta_seconds();
+    // upright and rising on screen even when the camera turns with the player
+    let screen = player_q.get_single().map_or(Quat::IDENTITY, |pt| screen_rotation(&gamedata, *camera_mode, pt));
+
+    for (entity, mut t, mut text, mut number) in &mut numbers {
+        number.timer.tick(time.delta());
+        if number.timer.finished() { commands.entity(entity).despawn(); continue; }
+
+        number.since_hit += dt;
+        t.rotation = screen;
+        t.translation += screen.mul_vec3((number.vel * dt).extend(0.0));
+        let alpha = number.timer.fraction_remaining();
+        text.sections[0].style.color.set_a(alpha);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::guns::GunConfig;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+// =======
+// STRUCTS
+// =======
+
+#[derive(Resource, Copy, Clone, Default, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Difficulty {
+    Easy, 
+    #[default]
+    Normal, 
+    Hard, 
+    Lunatic
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard, Difficulty::Lunatic];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Lunatic => "Lunatic",
+        }
+    }
+
+    /// `--difficulty Hard` or `--difficulty=Hard` on the command line, skips the menu
+    pub fn from_args() -> Option<Difficulty> {
+        let args: Vec<String> = std::env::args().collect();
+        let value = args.iter().enumerate().find_map(|(i, arg)| {
+            match arg.strip_prefix("--difficulty") {
+                Some("") => args.get(i + 1).cloned(),
+                Some(rest) => rest.strip_prefix('=').map(String::from),
+                None => None,
+            }
+        })?;
+
+        let difficulty = Difficulty::ALL.into_iter().find(|d| d.name().eq_ignore_ascii_case(&value));
+        if difficulty.is_none() {
+            warn!("unknown difficulty '{value}', expected one of Easy, Normal, Hard, Lunatic");
+        }
+        difficulty
+    }
+}
+
+
+/// multipliers applied to whatever the level files say
+#[derive(Deserialize)]
+pub struct Multipliers {
+    pub bullet_vel: f32,
+    pub fire_interval: f32,
+    pub bullet_count: f32, // only touches ring / spread guns, single shots stay single
+    pub damage: f32,
+    pub enemy_health: f32,
+}
+
+/// absolute values that win over both the level files and the multipliers
+#[derive(Deserialize, Default)]
+pub struct Overrides {
+    pub bullet_vel: Option<f32>,
+    pub timer_duration_millis: Option<u64>,
+    pub bullet_count: Option<u32>,
+    pub bullet_damage: Option<i32>,
+    pub enemy_health: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct DifficultyPreset {
+    pub multipliers: Multipliers,
+    #[serde(default)]
+    pub overrides: Overrides,
+}
+
+impl DifficultyPreset {
+    pub fn apply_gun(&self, mut gun: GunConfig) -> GunConfig {
+        let m = &self.multipliers;
+        let o = &self.overrides;
+
+        gun.bullet_vel = o.bullet_vel.unwrap_or(gun.bullet_vel * m.bullet_vel);
+        gun.timer_duration_millis = o.timer_duration_millis
+            .unwrap_or((gun.timer_duration_millis as f32 * m.fire_interval).round() as u64);
+        gun.bullet_damage = o.bullet_damage.unwrap_or((gun.bullet_damage as f32 * m.damage).round() as i32);
+
+        if gun.bullet_count > 1 {
+            gun.bullet_count = o.bullet_count
+                .unwrap_or((gun.bullet_count as f32 * m.bullet_count).round() as u32)
+                .max(1);
+        }
+        gun
+    }
+
+    pub fn apply_health(&self, health: i32) -> i32 {
+        self.overrides.enemy_health.unwrap_or((health as f32 * self.multipliers.enemy_health).round() as i32)
+    }
+}
+
+
+#[derive(Resource, Deserialize)]
+pub struct DifficultyPresets(pub HashMap<Difficulty, DifficultyPreset>);
+
+impl Default for DifficultyPresets {
+    fn default() -> DifficultyPresets {
+        let file = File::open("config/difficulty.json").unwrap();
+        let reader = BufReader::new(file);
+        let data: DifficultyPresets = serde_json::from_reader(reader).unwrap();
+        data
+    }
+}
+
+impl DifficultyPresets {
+    pub fn get(&self, difficulty: Difficulty) -> &DifficultyPreset {
+        self.0.get(&difficulty).unwrap()
+    }
+}
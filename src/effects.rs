@@ -0,0 +1,178 @@
+use crate::camera::{CameraRig, GameCamera};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+// Camera juice. Gameplay sends an `Impact` and the config decides how much the screen
+// shakes, how long the simulation freezes and how far the camera punches in.
+// Runs on real time so the shake keeps going through a hit-stop
+
+// =======
+// STRUCTS
+// =======
+
+#[derive(Event, Copy, Clone)]
+pub enum Impact {
+    PlayerHit { damage: i32 },
+    Bomb, // nothing spends bombs yet, here so the config can already tune it
+    EnemyDown,
+    BossPhase, // a boss went past one of its phase lines
+}
+
+impl Impact {
+    fn key(&self) -> ImpactKind {
+        match self {
+            Impact::PlayerHit { .. } => ImpactKind::PlayerHit,
+            Impact::Bomb => ImpactKind::Bomb,
+            Impact::EnemyDown => ImpactKind::EnemyDown,
+            Impact::BossPhase => ImpactKind::BossPhase,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub enum ImpactKind {
+    PlayerHit, Bomb, EnemyDown, BossPhase
+}
+
+/// what one kind of impact does to the camera, anything left out does nothing
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct ImpactEffect {
+    pub trauma: f32,         // added to the shake, which saturates at 1
+    pub hit_stop_s: f32,     // how long the simulation freezes
+    pub min_damage: i32,     // player hits weaker than this don't hit-stop
+    pub zoom: f32,           // punch in by this much, 0.1 shows 10% less of the world
+}
+
+#[derive(Deserialize)]
+pub struct ShakeConfig {
+    pub enabled: bool,
+    pub max_offset: f32,    // pixels at full trauma
+    pub max_angle_deg: f32, // degrees at full trauma
+    pub frequency: f32,     // how jittery it is
+    pub decay: f32,         // trauma lost per second
+}
+
+#[derive(Deserialize)]
+pub struct HitStopConfig {
+    pub enabled: bool,
+    pub max_s: f32, // hit-stops stack up to this
+}
+
+#[derive(Deserialize)]
+pub struct ZoomConfig {
+    pub enabled: bool,
+    pub speed: f32,   // how fast the camera eases toward the zoom it wants, per second
+    pub recover: f32, // how fast a punch wears off, per second
+}
+
+#[derive(Resource, Deserialize)]
+pub struct EffectsConfig {
+    pub shake: ShakeConfig,
+    pub hit_stop: HitStopConfig,
+    pub zoom: ZoomConfig,
+    pub impacts: HashMap<ImpactKind, ImpactEffect>,
+}
+
+impl Default for EffectsConfig {
+    fn default() -> EffectsConfig {
+        let file = File::open("config/effects.json").unwrap();
+        let reader = BufReader::new(file);
+        let data: EffectsConfig = serde_json::from_reader(reader).unwrap();
+        data
+    }
+}
+
+// ==========
+// COMPONENTS
+// ==========
+
+#[derive(Component, Default)]
+pub struct CameraEffects {
+    pub trauma: f32,
+    pub hit_stop_s: f32,
+    pub zoom_punch: f32,
+    pub zoom: f32, // what the projection is at right now, 0 is the normal view
+}
+
+// =======
+// SYSTEMS
+// =======
+
+/// sum of a few sines, smooth and never quite repeating, -1..1
+#[inline]
+fn wobble(t: f32, seed: f32) -> f32 {
+    (t * 1.0 + seed).sin() * 0.5 + (t * 2.3 + seed * 3.1).sin() * 0.3 + (t * 4.7 + seed * 7.3).sin() * 0.2
+}
+
+
+pub fn trigger(
+    config: Res<EffectsConfig>,
+    mut impacts: EventReader<Impact>,
+    mut time: ResMut<Time<Virtual>>,
+    mut camera_q: Query<&mut CameraEffects, With<GameCamera>>,
+) {
+    let Ok(mut fx) = camera_q.get_single_mut() else { impacts.clear(); return; };
+
+    for impact in impacts.read() {
+        let Some(effect) = config.impacts.get(&impact.key()) else { continue; };
+
+        if config.shake.enabled {
+            fx.trauma = (fx.trauma + effect.trauma).min(1.0);
+        }
+        if config.zoom.enabled {
+            fx.zoom_punch = fx.zoom_punch.max(effect.zoom);
+        }
+
+        let big_enough = match impact {
+            Impact::PlayerHit { damage } => *damage >= effect.min_damage,
+            _ => true,
+        };
+        if config.hit_stop.enabled && big_enough && effect.hit_stop_s > 0.0 {
+            fx.hit_stop_s = (fx.hit_stop_s + effect.hit_stop_s).min(config.hit_stop.max_s);
+            time.pause(); // fixed ticks come out of virtual time, so the simulation stops too
+        }
+    }
+}
+
+
+#[allow(clippy::type_complexity)]
+pub fn apply(
+    real: Res<Time<Real>>,
+    config: Res<EffectsConfig>,
+    mut time: ResMut<Time<Virtual>>,
+    mut camera_q: Query<(&mut Transform, &mut OrthographicProjection, &mut CameraEffects), (With<GameCamera>, With<CameraRig>)>,
+) {
+    let Ok((mut ct, mut projection, mut fx)) = camera_q.get_single_mut() else { return; };
+    let dt = real.delta_seconds();
+
+    if fx.hit_stop_s > 0.0 {
+        fx.hit_stop_s -= dt;
+        if fx.hit_stop_s <= 0.0 {
+            fx.hit_stop_s = 0.0;
+            time.unpause();
+        }
+    }
+
+    // follow_player put the camera at rest this frame, the shake goes on top.
+    // Squaring the trauma keeps small hits subtle and big ones violent
+    fx.trauma = (fx.trauma - config.shake.decay * dt).max(0.0);
+    let shake = fx.trauma * fx.trauma;
+    if shake > 0.0 {
+        let t = real.elapsed_seconds() * config.shake.frequency;
+        let offset = Vec2::new(wobble(t, 1.0), wobble(t, 2.0)) * config.shake.max_offset * shake;
+        let offset = ct.rotation.mul_vec3(offset.extend(0.0));
+        ct.translation += offset;
+        ct.rotate_z(wobble(t, 3.0) * config.shake.max_angle_deg.to_radians() * shake);
+    }
+
+    fx.zoom_punch = (fx.zoom_punch - config.zoom.recover * dt).max(0.0);
+    fx.zoom = fx.zoom.lerp(fx.zoom_punch, 1.0 - (-config.zoom.speed * dt).exp());
+    let scale = 1.0 - fx.zoom;
+    if (projection.scale - scale).abs() > f32::EPSILON {
+        projection.scale = scale;
+    }
+}
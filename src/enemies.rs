@@ -1,7 +1,12 @@
-use crate::health::Health;
+use crate::health::{Health, MaxHealth};
+use crate::effects::Impact;
 use crate::gamedata::*;
 use crate::guns::{ GunConfigs, Guns };
-use crate::animations::{ TopDownStates, AnimationState, AnimationTimer, SpriteSheetConfig};
+use crate::pickups::Drops;
+use crate::difficulty::{Difficulty, DifficultyPresets};
+use crate::animations::{ TopDownStates, AnimationState, AnimationTimer, SpriteSheetConfig, DirectionalSprite};
+use crate::anim_graph::AnimationParams;
+use crate::vfx::{Vfx, VfxKind};
 use bevy::prelude::*;
 use serde::Deserialize;
 use std::fs::File;
@@ -15,11 +20,31 @@ use itertools::izip;
 #[derive(Component)]
 pub struct Enemy;
 
+/// `phases` are the health fractions where a new phase starts, highest first
+#[derive(Deserialize, Clone)]
+pub struct BossConfig {
+    pub name: String,
+    #[serde(default)]
+    pub phases: Vec<f32>,
+}
+
+/// gets the big bar at the top of the screen instead of the small one over its head
+#[derive(Component)]
+pub struct Boss {
+    pub name: String,
+    pub phases: Vec<f32>,
+    pub phase: usize, // how many of `phases` it's gone past
+}
+
 #[derive(Deserialize)]
 pub struct EnemyConfig {
     pub pos_x: f32,
     pub pos_y: f32,
     pub health: i32,
+    #[serde(default)]
+    pub drops: Drops,
+    #[serde(default)]
+    pub boss: Option<BossConfig>,
 }
 
 impl EnemyConfig {
@@ -38,44 +63,75 @@ impl EnemyConfig {
 
 pub fn spawn(
     gamedata: Res<GameData>,
+    difficulty: Res<Difficulty>,
+    presets: Res<DifficultyPresets>,
     mut commands: Commands, 
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut vfx: EventWriter<Vfx>,
 ) {
     let gunconfigs_vec = GunConfigs::enemies();
     let sheets = SpriteSheetConfig::enemies();
     let enemies = EnemyConfig::enemies();
+    let preset = presets.get(*difficulty);
 
     for (gunconfigs, sheet, enemy) in izip!(gunconfigs_vec, sheets, enemies) {
-        let guns = Guns::from(gunconfigs);
+        let guns = Guns::from(gunconfigs.scaled(preset));
+        let animations = sheet.animations();
+        let first_frame = animations.get(TopDownStates::Prone.as_ref()).map_or(0, |clip| clip.first);
+        let state = AnimationState::new(TopDownStates::Prone, &animations);
         let mut t = Transform::from_scale(Vec3::splat(gamedata.player_size)); // turn this into one liner
         t.translation = Vec3::new(enemy.pos_x, enemy.pos_y, 0.0);
+        let directional = sheet.directional.clone();
+        let health = preset.apply_health(enemy.health);
 
-        commands.spawn((
+        let mut entity = commands.spawn((
             Enemy,
-            Health(100),
+            Health(health),
+            MaxHealth(health),
             guns,
+            enemy.drops,
             // animations stuff: Animations, AnimationState, AnimationTimer, SpriteSheetBundle
-            sheet.animations.clone(),
-            AnimationState::new(TopDownStates::Prone, false),
+            animations,
+            state,
+            sheet.graph(),
+            AnimationParams::default(),
             AnimationTimer(Timer::from_seconds(sheet.duration_s, TimerMode::Repeating)),
             SpriteSheetBundle {
                 transform: t,
                 //Transform::from_scale(Vec3::splat(gamedata.player_size)), // hmmmm
-                texture: asset_server.load(sheet.path),
+                texture: asset_server.load(sheet.path.clone()),
                 atlas: TextureAtlas { 
-                    index: sheet.animations.0.get("Prone").unwrap().first,
-                    layout: texture_atlases.add(TextureAtlasLayout::from_grid(
-                        Vec2::new(sheet.frame_width, sheet.frame_height), 
-                        sheet.columns, 
-                        sheet.rows,
-                        Some(Vec2::new(sheet.padding_x, sheet.padding_y)), 
-                        None
-                    ))
+                    index: first_frame,
+                    layout: texture_atlases.add(sheet.layout())
                 },
                 ..default()
             },
         ));
+        if let Some(directional) = directional {
+            entity.insert(DirectionalSprite::new(directional));
+        }
+        if let Some(boss) = enemy.boss {
+            let mut phases = boss.phases;
+            phases.sort_by(|a, b| b.total_cmp(a));
+            entity.insert(Boss { name: boss.name, phases, phase: 0 });
+        }
+        vfx.send(Vfx { kind: VfxKind::EnemySpawn, pos: t.translation.truncate(), target: Some(entity.id()) });
+    }
+}
+
+
+/// shakes things up every time a boss drops past one of its phase lines
+pub fn boss_phases(
+    mut impacts: EventWriter<Impact>,
+    mut bosses: Query<(&Health, &MaxHealth, &mut Boss)>,
+) {
+    for (health, max, mut boss) in &mut bosses {
+        let left = health.0 as f32 / max.0.max(1) as f32;
+        while boss.phases.get(boss.phase).is_some_and(|&line| left <= line) {
+            boss.phase += 1;
+            impacts.send(Impact::BossPhase);
+        }
     }
 }
 
@@ -1,5 +1,6 @@
 use bevy::prelude::*;
 use serde::Deserialize;
+use rand::rngs::StdRng;
 use std::fs::File;
 use std::io::BufReader;
 
@@ -12,10 +13,39 @@ pub enum EntityType {
 #[derive(Resource, Deserialize)]
 pub struct HitboxSize(f32);
 
+/// the only randomness the simulation may use, seeded so replays come out the same
+#[derive(Resource, Deref, DerefMut)]
+pub struct GameRng(pub StdRng);
 
-#[derive(Resource)]
+/// how movement input maps onto the world and what decides where the player faces
+#[derive(Copy, Clone, PartialEq, Deserialize)]
+pub enum ControlScheme {
+    Tank,      // moves relative to where the player faces, rotation turns the player (and the camera with it)
+    Screen,    // moves along the screen axes, faces whatever `facing` says
+    TwinStick, // moves along the screen axes, faces where the aim stick points, rotation keys when it's idle
+}
+
+#[derive(Copy, Clone, Deserialize)]
+pub enum Facing {
+    Mouse,
+    Fixed(f32), // degrees counter-clockwise from the top of the screen
+}
+
+/// order the lock-on key walks through the enemies in
+#[derive(Copy, Clone, Deserialize)]
+pub enum LockOrder {
+    Distance, // nearest first
+    Angle,    // clockwise around the player, starting from the top of the screen
+}
+
+
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum GameState {
-    Pause, InGame, Menu, Splash
+    Pause, InGame, 
+    #[default]
+    Menu, 
+    Splash,
+    Rebinding
 }
 
 
@@ -23,11 +53,18 @@ pub enum GameState {
 pub struct GameData {
     pub dt: f32,
     pub player_speed: f32,
+    pub player_health: i32,
+    pub player_lives: u32,
+    pub player_invulnerable_s: f32, // after losing a life
     pub player_size: f32,
     pub player_rotation_speed: f32,
-    pub width: f32,
-    pub height: f32,
+    pub control_scheme: ControlScheme,
+    pub facing: Facing,
+    pub mouse_aim: bool, // face the cursor in any scheme, replaces the rotation keys
     pub scaling: i32,
+    pub lock_order: LockOrder,
+    pub lock_reticle_radius: f32,
+    pub player_sprites: String, // sprite sheet config for the player
 }
 
 impl Default for GameData {
@@ -2,9 +2,14 @@
 use std::{fs::File, io::BufReader};
 
 use crate::enemies::Enemy;
-use crate::player::Player;
+use crate::player::{Player, PlayerStats};
 use crate::gamedata::EntityType;
-use crate::bullets::Bullet;
+use crate::bullets::{Bullet, BulletMaterial, BulletStyles};
+use crate::lockon::TargetLock;
+use crate::difficulty::DifficultyPreset;
+use crate::input::{Action, ActionState};
+use crate::animations::AnimationEvent;
+use crate::health::Dying;
 use serde::Deserialize;
 
 use bevy::{
@@ -16,32 +21,70 @@ use bevy::{
 // STRUCTS 
 // =======
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Copy)]
 pub enum QuatType {
     Nan, Identity, Default
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone, Copy)]
 pub enum TimerBehavior {
     Once, Repeating
 }
 
-#[derive(Deserialize)]
+/// How a gun picks what to aim at. Enemy guns only ever have the player to aim at,
+/// `Locked` falls back to the nearest enemy when the player has nothing locked
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+pub enum Targeting {
+    Nearest, 
+    #[default]
+    Locked, 
+    None
+}
+
+#[derive(Deserialize, Clone)]
 pub struct GunConfig {
     pub pattern: AimPattern,
     pub rotation: QuatType,
     pub timer_duration_millis: u64,
     pub timer_behavior: TimerBehavior,
     pub target: EntityType,
+    #[serde(default)]
+    pub targeting: Targeting,
     pub bullet_size: f32,
     pub bullet_vel: f32,
     pub bullet_damage: i32,
     pub bullet_color_r: f32,
     pub bullet_color_g: f32,
     pub bullet_color_b: f32,
+    // volleys: `bullet_count` bullets fanned evenly over `spread_deg` (360 makes a ring)
+    #[serde(default = "GunConfig::default_bullet_count")]
+    pub bullet_count: u32,
+    #[serde(default)]
+    pub spread_deg: f32,
+    // where the muzzle sits relative to the shooter, in the shooter's own frame
+    #[serde(default)]
+    pub offset_x: f32,
+    #[serde(default)]
+    pub offset_y: f32,
+    // fire on this animation event of the shooter instead of the timer, so shots match the art
+    #[serde(default)]
+    pub fire_on: Option<String>,
+    // a look from config/bullet_styles.json, plain circles when missing
+    #[serde(default)]
+    pub style: Option<String>,
+    // bullets sit harmless and grow for this long before they move and can hit
+    #[serde(default)]
+    pub spawn_delay_millis: u64,
+    // the shooter charges up for this long before each volley, timer guns only
+    #[serde(default)]
+    pub telegraph_millis: u64,
 }
 
-#[derive(Deserialize)]
+impl GunConfig {
+    fn default_bullet_count() -> u32 { 1 }
+}
+
+#[derive(Deserialize, Clone)]
 pub struct GunConfigs(Vec<GunConfig>);
 
 impl GunConfigs {
@@ -51,45 +94,52 @@ impl GunConfigs {
         let data: Vec<GunConfigs> = serde_json::from_reader(reader).unwrap();
         data
     }
+
+    pub fn scaled(self, preset: &DifficultyPreset) -> GunConfigs {
+        GunConfigs(self.0.into_iter().map(|gunconfig| preset.apply_gun(gunconfig)).collect())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ShotType {
+    pub name: String,
+    pub levels: Vec<GunConfigs>, // one full set of guns per power level
+}
+
+#[derive(Deserialize)]
+pub struct PlayerGunsConfig {
+    pub power_per_level: u32,
+    pub shot_types: Vec<ShotType>,
+}
+
+impl PlayerGunsConfig {
+    pub fn player() -> Self {
+        let file = File::open("config/player_guns.json").unwrap();
+        let reader = BufReader::new(file);
+        let data: PlayerGunsConfig = serde_json::from_reader(reader).unwrap();
+        data
+    }
 }
 
 // ==========
 // COMPONENTS
 // ==========
 
-#[derive(Component, Deserialize)]
+#[derive(Component, Deserialize, Clone, Copy)]
 pub enum AimPattern {
     Rotate, Snap, Spiral, PlayerInput 
 }
 
 impl AimPattern {       
-    //also shoots at player gun if gun is PlayerInput, not great having that there 
+    /// only points the gun, firing is up to the enemy_guns / player_guns systems
     #[inline]           
-    fn rotate_gun(
-        gun: &mut Gun,
-        shooter: &Transform,
-        target: &Transform,
-        shooter2target: Vec2,
-        t: &Res<Time>,
-        k: Option<&Res<ButtonInput<KeyCode>>>,
-        commands: &mut Commands,
-        meshes: &mut ResMut<Assets<Mesh>>,
-        materials: &mut ResMut<Assets<ColorMaterial>>,
-    ) {
-        match gun.pattern {
-            AimPattern::Snap => gun.rotation = Quat::from_rotation_arc(Vec3::Y, shooter2target.extend(0.)),
-            AimPattern::Rotate => gun.rotation *= Quat::from_rotation_z(AimPattern::get_rotation_angle(shooter2target, *target, t.delta_seconds())), // hm
-            AimPattern::Spiral => gun.rotation *= Quat::from_rotation_z(0.20),
-            AimPattern::PlayerInput => {
-                match k {
-                    Some(key) => {
-                        if key.just_pressed(KeyCode::KeyP) && gun.timer.finished() {
-                            Bullet::spawn_straight(gun, shooter, commands, meshes, materials);   
-                        }
-                    },
-                    None => {}
-                }
-            } 
+    fn rotate_gun(gun: &mut Gun, shooter: &Transform, shooter2target: Option<Vec2>, dt: f32) {
+        match (gun.pattern, shooter2target) {
+            (AimPattern::Snap, Some(s2t)) => gun.rotation = Quat::from_rotation_arc(Vec3::Y, s2t.extend(0.)),
+            (AimPattern::Rotate, Some(s2t)) => gun.rotation *= Quat::from_rotation_z(AimPattern::get_rotation_angle(s2t, Transform::from_rotation(gun.rotation), dt)),
+            (AimPattern::Snap | AimPattern::Rotate, None) => {} // nothing to aim at, keep pointing where it was
+            (AimPattern::Spiral, _) => gun.rotation *= Quat::from_rotation_z(0.20),
+            (AimPattern::PlayerInput, _) => gun.rotation = shooter.rotation, // shoots straight from entity transform
         }
     }
 
@@ -102,7 +152,7 @@ impl AimPattern {
         let right_dot_player = b_right.dot(b2p);
         let rotation_sign = -f32::copysign(1.0, right_dot_player);
         let max_angle = forward_dot_player.clamp(-1.0, 1.0).acos(); 
-        return rotation_sign * (f32::to_radians(90.0) * t).min(max_angle);
+        rotation_sign * (f32::to_radians(90.0) * t).min(max_angle)
     }
 }
 
@@ -116,10 +166,34 @@ pub struct Gun {
     pub color: Color,
     pub rotation: Quat,
     pub timer: Timer,
-    pub target: EntityType  
+    pub target: EntityType,
+    pub targeting: Targeting,
+    pub bullet_count: u32,
+    pub spread: f32,
+    pub offset: Vec2,
+    pub fire_on: Option<String>,
+    pub style: Option<String>,
+    pub spawn_delay: Duration,
+    pub telegraph: Duration,
 }
 
 impl Gun {
+    /// did the shooter's animation just hit this gun's event
+    #[inline]
+    fn cued(&self, shooter: Entity, cues: &[AnimationEvent]) -> bool {
+        self.fire_on.as_ref().is_some_and(|name| cues.iter().any(|cue| cue.entity == shooter && cue.name == *name))
+    }
+
+    /// 0 to 1 over the telegraph window before the timer fires, 0 the rest of the time.
+    /// animation cued guns can't know when they'll fire so they never charge
+    pub fn charge(&self) -> f32 {
+        if self.telegraph.is_zero() || self.fire_on.is_some() || self.timer.finished() { return 0.0; }
+        let remaining = self.timer.remaining();
+        if remaining > self.telegraph { return 0.0; }
+        1.0 - remaining.as_secs_f32() / self.telegraph.as_secs_f32()
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         pattern: AimPattern, 
         bullet_size: f32, 
@@ -128,7 +202,15 @@ impl Gun {
         color: Color, 
         rotation: Quat, 
         timer: Timer, 
-        target: EntityType
+        target: EntityType,
+        targeting: Targeting,
+        bullet_count: u32,
+        spread: f32,
+        offset: Vec2,
+        fire_on: Option<String>,
+        style: Option<String>,
+        spawn_delay: Duration,
+        telegraph: Duration
     ) -> Self {
         Gun { 
             pattern, 
@@ -138,7 +220,15 @@ impl Gun {
             color, 
             rotation, 
             timer, 
-            target 
+            target,
+            targeting,
+            bullet_count,
+            spread,
+            offset,
+            fire_on,
+            style,
+            spawn_delay,
+            telegraph
         }
     }
     
@@ -149,9 +239,9 @@ impl Gun {
             bullet_vel: gunconfig.bullet_vel,
             bullet_damage: gunconfig.bullet_damage,
             color: Color::rgb(
-                gunconfig.bullet_color_r as f32, 
-                gunconfig.bullet_color_g as f32, 
-                gunconfig.bullet_color_b as f32
+                gunconfig.bullet_color_r, 
+                gunconfig.bullet_color_g, 
+                gunconfig.bullet_color_b
             ),
             rotation: match gunconfig.rotation {
                 QuatType::Default => Quat::default(),
@@ -159,29 +249,21 @@ impl Gun {
                 QuatType::Nan => Quat::NAN
             },
             target: gunconfig.target,
+            targeting: gunconfig.targeting,
             timer: Timer::new(
                 Duration::from_millis(gunconfig.timer_duration_millis),
                 match gunconfig.timer_behavior {
                     TimerBehavior::Once => TimerMode::Once,
                     TimerBehavior::Repeating => TimerMode::Repeating,
                 }
-            )
-        }
-    }
-
-    pub fn player_gun() -> Self {
-        Gun { 
-            pattern: AimPattern::PlayerInput,
-            bullet_size: 1.0,
-            bullet_vel: 1000.0,
-            bullet_damage: 50,
-            color: Color::rgb(10.0, 10.0, 10.0),
-            rotation: Quat::default(), 
-            target: EntityType::Enemy,
-            timer: Timer::new(
-                Duration::from_millis(200),
-                TimerMode::Once
-            )
+            ),
+            bullet_count: gunconfig.bullet_count.max(1),
+            spread: gunconfig.spread_deg.to_radians(),
+            offset: Vec2::new(gunconfig.offset_x, gunconfig.offset_y),
+            fire_on: gunconfig.fire_on,
+            style: gunconfig.style,
+            spawn_delay: Duration::from_millis(gunconfig.spawn_delay_millis),
+            telegraph: Duration::from_millis(gunconfig.telegraph_millis),
         }
     }
 }
@@ -190,6 +272,7 @@ impl Gun {
 #[derive(Component)] 
 pub struct Guns(Vec<Gun>);
 
+
 impl Guns {
     pub fn new(guns: Vec<Gun>) -> Self {
         Self(guns)
@@ -205,28 +288,69 @@ impl Guns {
 }
 
 
+/// The player's selectable shot types. The guns actually fired live in `Guns`, which gets
+/// rebuilt from the current shot type's level whenever either of them changes
+#[derive(Component)]
+pub struct Arsenal {
+    pub shot_types: Vec<ShotType>,
+    pub power_per_level: u32,
+    pub current: usize,
+    pub level: usize,
+}
+
+impl Arsenal {
+    pub fn player() -> Self {
+        let config = PlayerGunsConfig::player();
+        Arsenal {
+            shot_types: config.shot_types,
+            power_per_level: config.power_per_level.max(1),
+            current: 0,
+            level: 0,
+        }
+    }
+
+    pub fn level_for(&self, power: u32) -> usize {
+        let levels = self.shot_types[self.current].levels.len();
+        ((power / self.power_per_level) as usize).min(levels - 1)
+    }
+
+    pub fn guns(&self) -> Guns {
+        Guns::from(self.shot_types[self.current].levels[self.level].clone())
+    }
+}
+
+
 // =======
 // SYSTEMS
 // =======
 
 pub fn enemy_guns(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut enemies_q: Query<(&Transform, &mut Guns), With<Enemy>>,
+    styles: Res<BulletStyles>,
+    mut materials: ResMut<Assets<BulletMaterial>>,
+    mut enemies_q: Query<(Entity, &Transform, &mut Guns), With<Enemy>>,
     player_q: Query<&Transform, With<Player>>,
+    mut cues: EventReader<AnimationEvent>,
     t: Res<Time>,
 ) {
-    for (et, mut guns) in enemies_q.iter_mut() {
+    let cues: Vec<AnimationEvent> = cues.read().cloned().collect();
+
+    for (enemy, et, mut guns) in enemies_q.iter_mut() {
         if let Ok(pt) = player_q.get_single() {
             let e2p = (pt.translation.truncate() - et.translation.truncate()).normalize();
 
             for gun in guns.0.iter_mut() {
-                AimPattern::rotate_gun(gun, et, pt, e2p, &t, None, &mut commands, &mut meshes, &mut materials);
+                let aim = if gun.targeting == Targeting::None { None } else { Some(e2p) };
+                AimPattern::rotate_gun(gun, et, aim, t.delta_seconds());
 
-                gun.timer.tick(t.delta());
-                if gun.timer.just_finished() { 
-                    Bullet::spawn(gun, et, &mut commands, &mut meshes, &mut materials); 
+                let fire = if gun.fire_on.is_some() {
+                    gun.cued(enemy, &cues)
+                } else {
+                    gun.timer.tick(t.delta());
+                    gun.timer.just_finished()
+                };
+                if fire { 
+                    Bullet::spawn(gun, enemy, et, &styles, &mut commands, &mut materials); 
                 }
             }
         } else {
@@ -236,28 +360,93 @@ pub fn enemy_guns(
 }
 
 
+/// rings closing in on the enemy, brighter the closer its next volley is
+pub fn draw_telegraphs(
+    mut gizmos: Gizmos,
+    enemies_q: Query<(&Transform, &Guns), With<Enemy>>,
+) {
+    for (et, guns) in &enemies_q {
+        for gun in guns.0.iter() {
+            let charge = gun.charge();
+            if charge <= 0.0 { continue; }
+
+            let pos = et.translation.truncate();
+            let radius = et.scale.x * (4.0 - 3.0 * charge);
+            let color = gun.color.with_a(charge);
+            gizmos.circle_2d(pos, radius, color).segments(48);
+            gizmos.circle_2d(pos, radius * 0.6, color.with_a(charge * 0.5)).segments(32);
+        }
+    }
+}
+
+
+pub fn player_arsenal(
+    actions: Res<ActionState>,
+    mut player_q: Query<(&mut Arsenal, &mut Guns, &PlayerStats), With<Player>>,
+) {
+    if let Ok((mut arsenal, mut guns, stats)) = player_q.get_single_mut() {
+        let mut changed = false;
+
+        if actions.just_pressed(Action::CycleShot) {
+            arsenal.current = (arsenal.current + 1) % arsenal.shot_types.len();
+            changed = true;
+        }
+
+        let level = arsenal.level_for(stats.power);
+        if level != arsenal.level {
+            arsenal.level = level;
+            changed = true;
+        }
+
+        if changed { *guns = arsenal.guns(); }
+    }
+}
+
+
+#[allow(clippy::too_many_arguments)]
 pub fn player_guns(
     t: Res<Time>,
-    k: Res<ButtonInput<KeyCode>>,
-    enemy_q: Query<&Transform, With<Enemy>>,
+    actions: Res<ActionState>,
+    enemy_q: Query<&Transform, (With<Enemy>, Without<Dying>)>,
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut players_q: Query<(&Transform, &mut Guns), With<Player>>,
+    styles: Res<BulletStyles>,
+    mut materials: ResMut<Assets<BulletMaterial>>,
+    mut players_q: Query<(Entity, &Transform, &mut Guns, Option<&TargetLock>), With<Player>>,
+    mut cues: EventReader<AnimationEvent>,
 ) {
-    for (pt, mut guns) in players_q.iter_mut() {
-        for et in enemy_q.iter() {
-            let p2e = (et.translation.truncate() - pt.translation.truncate()).normalize();
+    let firing = actions.pressed(Action::Fire);
+    let cues: Vec<AnimationEvent> = cues.read().cloned().collect();
 
-            for gun in guns.0.iter_mut() {
-                AimPattern::rotate_gun(gun, pt, et, p2e, &t, Some(&k), &mut commands, &mut meshes, &mut materials);
+    for (player, pt, mut guns, lock) in players_q.iter_mut() {
+        let nearest = enemy_q.iter()
+            .map(|et| et.translation)
+            .min_by(|a, b| a.distance_squared(pt.translation).total_cmp(&b.distance_squared(pt.translation)));
+        let locked = lock
+            .and_then(|lock| lock.0)
+            .and_then(|e| enemy_q.get(e).ok())
+            .map(|et| et.translation);
 
-                gun.timer.tick(t.delta());
-                if gun.timer.just_finished() {
-                    Bullet::spawn(gun, pt, &mut commands, &mut meshes, &mut materials);
+        for gun in guns.0.iter_mut() {
+            let target = match gun.targeting {
+                Targeting::Nearest => nearest,
+                Targeting::Locked => locked.or(nearest),
+                Targeting::None => None,
+            };
+            let p2t = target.map(|tt| (tt.truncate() - pt.translation.truncate()).normalize_or_zero());
+            AimPattern::rotate_gun(gun, pt, p2t, t.delta_seconds());
+
+            if gun.fire_on.is_some() {
+                if firing && gun.cued(player, &cues) {
+                    Bullet::spawn(gun, player, pt, &styles, &mut commands, &mut materials);
                 }
+                continue;
+            }
+
+            gun.timer.tick(t.delta());
+            if firing && gun.timer.finished() {
+                Bullet::spawn(gun, player, pt, &styles, &mut commands, &mut materials);
+                gun.timer.reset();
             }
         }
     }
 }
-
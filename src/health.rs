@@ -1,26 +1,123 @@
 use crate::player::Player;
+use crate::pickups::{Drops, Pickup, PickupData};
+use crate::gamedata::{GameData, GameRng};
+use crate::enemies::Enemy;
+use crate::effects::Impact;
+use crate::anim_graph::{AnimationGraph, AnimationParams};
+use crate::animations::{AnimationState, DirectionalSprite};
+use crate::guns::Guns;
+use crate::bullets::Bullet;
+use crate::vfx::{Vfx, VfxKind};
 use bevy::app::AppExit;
 use bevy::prelude::*;
 
 #[derive(Component)]
 pub struct Health(pub i32);
 
-pub fn handle(mut commands: Commands, health_q: Query<(Entity, &Health)>) {
-    for (entity, health ) in &health_q {
+/// what `Health` starts at, for the bars and for coming back after losing a life
+#[derive(Component)]
+pub struct MaxHealth(pub i32);
+
+/// tries left including the current one, the player only dies on the last
+#[derive(Component)]
+pub struct Lives(pub u32);
+
+/// bullets go right through for a while after losing a life, the sprite blinks meanwhile
+#[derive(Component)]
+pub struct Invulnerable(pub Timer);
+
+/// Out of health and out of the fight, but still around while its graph plays the death clip.
+/// The timer gives up on clips that never end
+#[derive(Component)]
+pub struct Dying(pub Timer);
+
+const MAX_DYING_S: f32 = 3.0;
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn handle(
+    mut commands: Commands, 
+    gamedata: Res<GameData>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    pickup_data: Res<PickupData>,
+    mut rng: ResMut<GameRng>,
+    mut impacts: EventWriter<Impact>,
+    mut vfx: EventWriter<Vfx>,
+    mut health_q: Query<(Entity, &mut Health, &mut Transform, Option<&Drops>, Option<&mut AnimationParams>, Option<&mut Lives>, Option<&MaxHealth>, Has<Enemy>)>,
+    bullets: Query<(Entity, &Transform, &Bullet), Without<Health>>,
+) {
+    for (entity, mut health, mut transform, drops, params, lives, max, is_enemy) in &mut health_q {
         if health.0 <= 0 { 
-            commands.entity(entity).despawn(); 
+            if let (Some(mut lives), Some(max)) = (lives, max) {
+                if lives.0 > 1 {
+                    // back to where `player::spawn` put it, with a moment to get away from whatever is there
+                    lives.0 -= 1;
+                    health.0 = max.0;
+                    transform.translation = Vec3::new(0.0, 0.0, transform.translation.z);
+                    commands.entity(entity).insert(Invulnerable(Timer::from_seconds(gamedata.player_invulnerable_s, TimerMode::Once)));
+                    vfx.send(Vfx { kind: VfxKind::PlayerSpawn, pos: transform.translation.truncate(), target: Some(entity) });
+                    continue;
+                }
+            }
+            if is_enemy { 
+                impacts.send(Impact::EnemyDown); 
+                vfx.send(Vfx { kind: VfxKind::EnemyDeath, pos: transform.translation.truncate(), target: None });
+                // whatever it had in the air goes with it
+                for (bullet_entity, bt, bullet) in &bullets {
+                    if bullet.shooter != entity { continue; }
+                    commands.entity(bullet_entity).despawn();
+                    vfx.send(Vfx { kind: VfxKind::BulletCancel, pos: bt.translation.truncate(), target: None });
+                }
+            }
+            if let Some(drops) = drops {
+                Pickup::spawn_drops(drops, transform.translation, &pickup_data, &mut rng, &mut commands, &mut meshes, &mut materials);
+            }
+            // without health and guns nothing hits it and it stops shooting, `finish_dying` takes it from here
+            if let Some(mut params) = params { params.set("dead", true); }
+            commands.entity(entity)
+                .remove::<(Health, Guns)>()
+                .insert(Dying(Timer::from_seconds(MAX_DYING_S, TimerMode::Once)));
         }
     }
 }
 
-pub fn quit_on_player_death(
-    mut exit: EventWriter<AppExit>, 
-    player_health_query: Query<&Health, With<Player>>
+/// counts the invulnerability down and blinks the sprite while it lasts
+pub fn invulnerability(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Invulnerable, Option<&mut Sprite>)>,
 ) {
-    if let Ok(health) = player_health_query.get_single() {
-        if health.0 <= 0 {
-            exit.send(AppExit);
+    for (entity, mut invulnerable, sprite) in &mut query {
+        invulnerable.0.tick(time.delta());
+        let done = invulnerable.0.finished();
+        if let Some(mut sprite) = sprite {
+            let blink = (invulnerable.0.elapsed_secs() * 10.0) as u32 % 2 == 0;
+            sprite.color.set_a(if blink && !done { 0.3 } else { 1.0 });
         }
+        if done { commands.entity(entity).remove::<Invulnerable>(); }
     }
 }
 
+
+/// Gets rid of the dying once their death clip has played out, straight away when their graph has
+/// nothing to show for `dead`. The player isn't despawned, it ends the game instead and stays until
+/// the app is gone so `scores::record_on_exit` can still read its stats
+#[allow(clippy::type_complexity)]
+pub fn finish_dying(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut exit: EventWriter<AppExit>,
+    mut dying_q: Query<(Entity, &mut Dying, Option<&AnimationState>, Option<&AnimationGraph>, Has<DirectionalSprite>, Has<Player>)>,
+) {
+    for (entity, mut dying, state, graph, directional, is_player) in &mut dying_q {
+        dying.0.tick(time.delta());
+
+        // directional sheets pick their own clips, their graph never runs
+        let shows_death = !directional && graph.is_some_and(|graph| graph.reacts_to("dead"));
+        let played_out = graph.is_some_and(|graph| graph.is_in_state_for("dead")) && state.is_some_and(|state| state.done);
+        if shows_death && !played_out && !dying.0.finished() { continue; }
+
+        if is_player { exit.send(AppExit); } 
+        else { commands.entity(entity).despawn(); }
+    }
+}
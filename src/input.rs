@@ -0,0 +1,343 @@
+use crate::camera::{GameCamera, cursor_world_position};
+use crate::gamedata::GameData;
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy::utils::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+// gameplay systems never look at keys or gamepads directly, they ask `ActionState` about actions
+// and `Controls` decides which keys / buttons trigger which action
+
+const CONTROLS_PATH: &str = "config/controls.json";
+
+// =======
+// STRUCTS
+// =======
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp, MoveDown, MoveLeft, MoveRight,
+    RotateLeft, RotateRight,
+    Fire, Focus, Bomb,
+    CycleShot, CycleTarget, CycleCamera,
+    Pause,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::MoveUp, Action::MoveDown, Action::MoveLeft, Action::MoveRight,
+        Action::RotateLeft, Action::RotateRight,
+        Action::Fire, Action::Focus, Action::Bomb,
+        Action::CycleShot, Action::CycleTarget, Action::CycleCamera,
+        Action::Pause,
+    ];
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    GamepadButton(GamepadButtonType), // on any connected gamepad
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{key:?}"),
+            Binding::GamepadButton(button) => format!("Pad {button:?}"),
+            Binding::Mouse(button) => format!("Mouse {button:?}"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Stick {
+    Left, Right
+}
+
+impl Stick {
+    fn axes(&self) -> (GamepadAxisType, GamepadAxisType) {
+        match self {
+            Stick::Left => (GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY),
+            Stick::Right => (GamepadAxisType::RightStickX, GamepadAxisType::RightStickY),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum RotationInput {
+    RightStickX,
+    Triggers, // left trigger turns left, right trigger turns right
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GamepadControls {
+    pub deadzone: f32, // radial, stick input below this is ignored and the rest is rescaled to 0..1
+    pub move_stick: Stick,
+    pub rotation: RotationInput,
+}
+
+
+#[derive(Resource, Serialize, Deserialize)]
+pub struct Controls {
+    // every action can have any number of bindings, any of them triggers it
+    pub actions: BTreeMap<Action, Vec<Binding>>, // BTreeMap keeps the file in a stable order when saving
+    pub gamepad: GamepadControls,
+}
+
+impl Default for Controls {
+    fn default() -> Controls {
+        let file = File::open(CONTROLS_PATH).unwrap();
+        let reader = BufReader::new(file);
+        let data: Controls = serde_json::from_reader(reader).unwrap();
+        data
+    }
+}
+
+impl Controls {
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.actions.get(&action).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        match File::create(CONTROLS_PATH) {
+            Ok(file) => {
+                if let Err(e) = serde_json::to_writer_pretty(BufWriter::new(file), self) {
+                    error!("could not write {CONTROLS_PATH}: {e}");
+                }
+            },
+            Err(e) => error!("could not create {CONTROLS_PATH}: {e}"),
+        }
+    }
+}
+
+
+/// Everything one simulation tick reads from the input devices. This is also what
+/// replays store, so a tick fed back from a file plays out exactly like the original
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TickInput {
+    pub pressed: Vec<Action>, // sorted, so equal inputs compare equal
+    pub movement: Vec2,       // x right, y up, length 0..1
+    pub rotation: f32,        // -1..1, positive turns left
+    pub aim: Vec2,            // right stick, screen space, used by the twin-stick scheme
+    pub cursor: Option<Vec2>, // mouse in world space, if it's over the window
+}
+
+
+/// which actions are held this tick, edges are worked out against the previous tick.
+/// Movement and rotation are analog, keys just push them all the way
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    previous: HashSet<Action>,
+    pub movement: Vec2,
+    pub rotation: f32,
+    pub aim: Vec2,
+    pub cursor: Option<Vec2>,
+}
+
+impl ActionState {
+    #[inline]
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    #[inline]
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action) && !self.previous.contains(&action)
+    }
+
+    #[inline]
+    pub fn just_released(&self, action: Action) -> bool {
+        !self.pressed.contains(&action) && self.previous.contains(&action)
+    }
+
+    /// moves on to the next tick
+    pub fn advance(&mut self, input: &TickInput) {
+        std::mem::swap(&mut self.previous, &mut self.pressed);
+        self.pressed.clear();
+        self.pressed.extend(input.pressed.iter().copied());
+        self.movement = input.movement;
+        self.rotation = input.rotation;
+        self.aim = input.aim;
+        self.cursor = input.cursor;
+    }
+
+    pub fn snapshot(&self) -> TickInput {
+        let mut pressed: Vec<Action> = self.pressed.iter().copied().collect();
+        pressed.sort();
+        TickInput { pressed, movement: self.movement, rotation: self.rotation, aim: self.aim, cursor: self.cursor }
+    }
+
+    /// what was held the tick before, sorted
+    pub fn previous(&self) -> Vec<Action> {
+        let mut previous: Vec<Action> = self.previous.iter().copied().collect();
+        previous.sort();
+        previous
+    }
+
+    /// puts back a tick exactly as it was recorded, edges included
+    pub fn restore(&mut self, previous: &[Action], input: &TickInput) {
+        self.advance(input);
+        self.previous.clear();
+        self.previous.extend(previous.iter().copied());
+    }
+}
+
+// =======
+// SYSTEMS
+// =======
+
+/// radial deadzone, rescaled so the output still goes smoothly from 0 to 1
+#[inline]
+fn apply_deadzone(stick: Vec2, deadzone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= deadzone { return Vec2::ZERO; }
+    let scaled = ((length - deadzone) / (1.0 - deadzone)).min(1.0);
+    stick / length * scaled
+}
+
+
+/// samples the devices once per simulation tick
+#[allow(clippy::too_many_arguments)]
+pub fn update_actions(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    window_q: Query<&Window, With<PrimaryWindow>>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
+    controls: Res<Controls>,
+    gamedata: Res<GameData>,
+    mut actions: ResMut<ActionState>,
+) {
+    let mut pressed = Vec::new();
+    for action in Action::ALL {
+        let held = controls.bindings(action).iter().any(|binding| match binding {
+            Binding::Key(key) => keys.pressed(*key),
+            Binding::GamepadButton(button) => gamepads.iter().any(|gamepad| buttons.pressed(GamepadButton::new(gamepad, *button))),
+            Binding::Mouse(button) => gamedata.mouse_aim && mouse.pressed(*button), // clicks only count while aiming with the mouse
+        });
+        if held { pressed.push(action); }
+    }
+
+    let axis = |positive: Action, negative: Action| {
+        pressed.contains(&positive) as i32 as f32 - pressed.contains(&negative) as i32 as f32
+    };
+    let mut movement = Vec2::new(axis(Action::MoveRight, Action::MoveLeft), axis(Action::MoveUp, Action::MoveDown));
+    let mut rotation = axis(Action::RotateLeft, Action::RotateRight);
+    let mut aim = Vec2::ZERO;
+
+    let pad = &controls.gamepad;
+    for gamepad in gamepads.iter() {
+        let read = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.0);
+
+        let (x, y) = pad.move_stick.axes();
+        movement += apply_deadzone(Vec2::new(read(x), read(y)), pad.deadzone);
+        aim += apply_deadzone(Vec2::new(read(GamepadAxisType::RightStickX), read(GamepadAxisType::RightStickY)), pad.deadzone);
+
+        rotation += match pad.rotation {
+            RotationInput::RightStickX => -apply_deadzone(Vec2::new(read(GamepadAxisType::RightStickX), 0.0), pad.deadzone).x,
+            RotationInput::Triggers => {
+                let trigger = |button_type| button_axes.get(GamepadButton::new(gamepad, button_type)).unwrap_or(0.0);
+                trigger(GamepadButtonType::LeftTrigger2) - trigger(GamepadButtonType::RightTrigger2)
+            }
+        };
+    }
+
+    let cursor = window_q.get_single().ok()
+        .zip(camera_q.get_single().ok())
+        .and_then(|(window, (camera, ct))| cursor_world_position(window, camera, ct));
+
+    actions.advance(&TickInput {
+        pressed,
+        movement: movement.clamp_length_max(1.0),
+        rotation: rotation.clamp(-1.0, 1.0),
+        aim: aim.clamp_length_max(1.0),
+        cursor,
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::input::InputPlugin;
+    use bevy::input::gamepad::{GamepadAxisChangedEvent, GamepadButtonChangedEvent, GamepadConnection, GamepadConnectionEvent, GamepadInfo};
+
+    const PAD: Gamepad = Gamepad { id: 0 };
+    const DEADZONE: f32 = 0.2;
+
+    /// just the input plugin and `update_actions`, with a gamepad plugged in
+    fn app() -> App {
+        let mut controls = Controls::default();
+        controls.gamepad.deadzone = DEADZONE;
+        controls.gamepad.move_stick = Stick::Left;
+
+        let mut app = App::new();
+        app.add_plugins(InputPlugin)
+            .insert_resource(controls)
+            .insert_resource(GameData::default())
+            .init_resource::<ActionState>()
+            .add_systems(Update, update_actions);
+        app.world.send_event(GamepadConnectionEvent::new(PAD, GamepadConnection::Connected(GamepadInfo { name: "test pad".to_string() })));
+        app.update();
+        app
+    }
+
+    fn push_left_stick(app: &mut App, x: f32) -> Vec2 {
+        app.world.send_event(GamepadAxisChangedEvent::new(PAD, GamepadAxisType::LeftStickX, x));
+        app.update();
+        app.world.resource::<ActionState>().movement
+    }
+
+    /// the first binding of `action` that `pick` accepts
+    fn binding<T>(action: Action, pick: impl Fn(&Binding) -> Option<T>) -> T {
+        Controls::default().bindings(action).iter().find_map(pick).expect("controls.json should bind it")
+    }
+
+    #[test]
+    fn stick_inside_deadzone_does_nothing() {
+        let mut app = app();
+        assert_eq!(push_left_stick(&mut app, DEADZONE * 0.5), Vec2::ZERO);
+        assert_eq!(push_left_stick(&mut app, -DEADZONE), Vec2::ZERO);
+    }
+
+    #[test]
+    fn movement_scales_with_stick_magnitude() {
+        let mut app = app();
+        let half = push_left_stick(&mut app, 0.6);
+        let full = push_left_stick(&mut app, 1.0);
+
+        let expected = (0.6 - DEADZONE) / (1.0 - DEADZONE);
+        assert!((half.x - expected).abs() < 1e-5, "{half:?}");
+        assert!((full.x - 1.0).abs() < 1e-5, "{full:?}");
+        assert_eq!(half.y, 0.0);
+    }
+
+    #[test]
+    fn face_button_fires_like_the_key() {
+        let key = binding(Action::Fire, |b| match b { Binding::Key(key) => Some(*key), _ => None });
+        let button = binding(Action::Fire, |b| match b { Binding::GamepadButton(button) => Some(*button), _ => None });
+
+        let mut by_key = app();
+        by_key.world.resource_mut::<ButtonInput<KeyCode>>().press(key);
+        by_key.update();
+
+        let mut by_pad = app();
+        by_pad.world.send_event(GamepadButtonChangedEvent::new(PAD, button, 1.0));
+        by_pad.update();
+
+        for app in [&by_key, &by_pad] {
+            let actions = app.world.resource::<ActionState>();
+            assert!(actions.pressed(Action::Fire));
+            assert!(actions.just_pressed(Action::Fire));
+        }
+        assert_eq!(by_key.world.resource::<ActionState>().snapshot().pressed, by_pad.world.resource::<ActionState>().snapshot().pressed);
+    }
+}
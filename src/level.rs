@@ -0,0 +1,224 @@
+use crate::player::Player;
+use crate::camera::GameCamera;
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+
+// =======
+// STRUCTS
+// =======
+
+/// the playfield, centered on the origin
+#[derive(Copy, Clone, Deserialize)]
+pub enum Arena {
+    Rect { half_width: f32, half_height: f32 },
+    Circle { radius: f32 },
+}
+
+impl Arena {
+    /// half the size of the box around the arena
+    pub fn half_extents(&self) -> Vec2 {
+        match *self {
+            Arena::Rect { half_width, half_height } => Vec2::new(half_width, half_height),
+            Arena::Circle { radius } => Vec2::splat(radius),
+        }
+    }
+
+    /// is `pos` inside the arena grown by `margin` on every side
+    pub fn contains(&self, pos: Vec2, margin: f32) -> bool {
+        match *self {
+            Arena::Rect { half_width, half_height } => pos.x.abs() <= half_width + margin && pos.y.abs() <= half_height + margin,
+            Arena::Circle { radius } => pos.length() <= radius + margin,
+        }
+    }
+
+    /// the closest point to `pos` that's at least `inset` inside the edge
+    pub fn clamp(&self, pos: Vec2, inset: f32) -> Vec2 {
+        match *self {
+            Arena::Rect { half_width, half_height } => {
+                let room = Vec2::new(half_width - inset, half_height - inset).max(Vec2::ZERO);
+                pos.clamp(-room, room)
+            },
+            Arena::Circle { radius } => pos.clamp_length_max((radius - inset).max(0.0)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Border {
+    pub visible: bool,
+    pub color_r: f32,
+    pub color_g: f32,
+    pub color_b: f32,
+}
+
+/// A picture (or plain color with no `path`) behind everything. `width` / `height` are the
+/// size of the whole picture, or of one tile when it's tiled
+#[derive(Deserialize)]
+pub struct BackgroundLayer {
+    pub path: Option<String>,
+    pub width: f32,
+    pub height: f32,
+    pub z: f32, // negative, further back the lower it is
+    pub color_r: f32, // tint, multiplies the picture
+    pub color_g: f32,
+    pub color_b: f32,
+    #[serde(default)]
+    pub parallax: f32, // 0 sits still in the world, 1 sticks to the camera, in between looks far away
+    #[serde(default)]
+    pub scroll_x: f32, // pixels per second, a vertical scroller just sets scroll_y
+    #[serde(default)]
+    pub scroll_y: f32,
+    #[serde(default)]
+    pub tiled: bool, // repeat forever in every direction instead of drawing it once
+}
+
+#[derive(Resource, Deserialize)]
+pub struct Level {
+    pub arena: Arena,
+    pub cull_margin: f32, // bullets and pickups get this far past the edge before they're dropped
+    pub border: Border,
+    #[serde(default)]
+    pub backgrounds: Vec<BackgroundLayer>,
+}
+
+impl Default for Level {
+    fn default() -> Level {
+        let file = File::open("config/level.json").unwrap();
+        let reader = BufReader::new(file);
+        let data: Level = serde_json::from_reader(reader).unwrap();
+        data
+    }
+}
+
+// ==========
+// COMPONENTS
+// ==========
+
+#[derive(Component)]
+pub struct Background;
+
+#[derive(Component)]
+pub struct Parallax {
+    pub factor: f32,
+    pub scroll: Vec2,
+    pub offset: Vec2, // how far it has scrolled by itself so far
+    pub tile: Option<Vec2>,
+    pub textured: bool, // plain colors cover the view as they are, pictures get tiled
+}
+
+// =======
+// SYSTEMS
+// =======
+
+pub fn spawn_backgrounds(
+    mut commands: Commands,
+    level: Res<Level>,
+    asset_server: Res<AssetServer>,
+) {
+    for layer in level.backgrounds.iter() {
+        commands.spawn((
+            Background,
+            Parallax {
+                factor: layer.parallax,
+                scroll: Vec2::new(layer.scroll_x, layer.scroll_y),
+                offset: Vec2::ZERO,
+                tile: layer.tiled.then_some(Vec2::new(layer.width, layer.height)),
+                textured: layer.path.is_some(),
+            },
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(layer.color_r, layer.color_g, layer.color_b),
+                    custom_size: Some(Vec2::new(layer.width, layer.height)),
+                    ..default()
+                },
+                texture: layer.path.as_ref().map(|path| asset_server.load(path.clone())).unwrap_or_default(),
+                transform: Transform::from_xyz(0.0, 0.0, layer.z),
+                ..default()
+            },
+        ));
+    }
+}
+
+
+/// Moves the layers against the camera. A tiled layer is one sprite just big enough to cover
+/// the view at any rotation, that jumps back a whole tile whenever it drifts too far
+#[allow(clippy::type_complexity)]
+pub fn scroll_backgrounds(
+    time: Res<Time>,
+    images: Res<Assets<Image>>,
+    camera_q: Query<(&Transform, &OrthographicProjection), With<GameCamera>>,
+    mut layer_q: Query<(&mut Transform, &mut Sprite, &mut Parallax, &Handle<Image>, Option<&ImageScaleMode>, Entity), (With<Background>, Without<GameCamera>)>,
+    mut commands: Commands,
+) {
+    let Ok((ct, projection)) = camera_q.get_single() else { return; };
+    let camera = ct.translation.truncate();
+    let view = projection.area.size().length(); // the diagonal, so rotating never shows an edge
+
+    for (mut lt, mut sprite, mut parallax, image, scale_mode, entity) in &mut layer_q {
+        let scroll = parallax.scroll * time.delta_seconds();
+        parallax.offset += scroll;
+        let origin = camera * parallax.factor + parallax.offset;
+
+        let Some(tile) = parallax.tile else {
+            lt.translation.x = origin.x;
+            lt.translation.y = origin.y;
+            continue;
+        };
+
+        // stays on the camera, shifted by however far into a tile the pattern is
+        let drift = origin - camera;
+        let wrapped = drift - (drift / tile).round() * tile;
+        lt.translation.x = camera.x + wrapped.x;
+        lt.translation.y = camera.y + wrapped.y;
+
+        let tiles = (Vec2::splat(view) / tile).ceil() + 2.0;
+        let size = tiles * tile;
+
+        // the tile size is only known relative to the picture once it has loaded. Tiling only
+        // takes one stretch for both axes, so it's fitted to the width and the sprite gets
+        // squashed vertically to bring the height to `tile.y` as well
+        let picture = images.get(image).filter(|_| parallax.textured).map(|picture| picture.size().as_vec2());
+        let squash = picture.map_or(1.0, |picture| (tile.y * picture.x) / (tile.x * picture.y));
+        let local_size = Vec2::new(size.x, size.y / squash);
+        if sprite.custom_size != Some(local_size) { sprite.custom_size = Some(local_size); }
+        lt.scale.y = squash;
+
+        if let (Some(picture), None) = (picture, scale_mode) {
+            commands.entity(entity).insert(ImageScaleMode::Tiled {
+                tile_x: true,
+                tile_y: true,
+                stretch_value: tile.x / picture.x,
+            });
+        }
+    }
+}
+
+
+/// keeps the player's whole hitbox inside the arena
+pub fn clamp_player(
+    level: Res<Level>,
+    mut player_q: Query<&mut Transform, With<Player>>,
+) {
+    if let Ok(mut pt) = player_q.get_single_mut() {
+        let clamped = level.arena.clamp(pt.translation.truncate(), pt.scale.x);
+        pt.translation.x = clamped.x;
+        pt.translation.y = clamped.y;
+    }
+}
+
+
+pub fn draw_border(level: Res<Level>, mut gizmos: Gizmos) {
+    if !level.border.visible { return; }
+
+    let color = Color::rgb(level.border.color_r, level.border.color_g, level.border.color_b);
+    match level.arena {
+        Arena::Rect { half_width, half_height } => {
+            gizmos.rect_2d(Vec2::ZERO, 0.0, Vec2::new(half_width, half_height) * 2.0, color);
+        },
+        Arena::Circle { radius } => {
+            gizmos.circle_2d(Vec2::ZERO, radius, color).segments(128);
+        },
+    }
+}
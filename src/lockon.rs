@@ -0,0 +1,89 @@
+use crate::enemies::Enemy;
+use crate::player::Player;
+use crate::camera::{CameraMode, screen_rotation};
+use crate::gamedata::{GameData, LockOrder};
+use crate::input::{Action, ActionState};
+use crate::health::Dying;
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+
+// ==========
+// COMPONENTS
+// ==========
+
+/// the enemy the player has locked on to, if any
+#[derive(Component, Default)]
+pub struct TargetLock(pub Option<Entity>);
+
+// =======
+// SYSTEMS
+// =======
+
+#[allow(clippy::type_complexity)]
+pub fn cycle_target(
+    actions: Res<ActionState>,
+    data: Res<GameData>,
+    camera_mode: Res<CameraMode>,
+    enemy_q: Query<(Entity, &Transform), (With<Enemy>, Without<Dying>)>,
+    mut player_q: Query<(&Transform, &mut TargetLock), With<Player>>,
+) {
+    if !actions.just_pressed(Action::CycleTarget) { return; }
+
+    if let Ok((pt, mut lock)) = player_q.get_single_mut() {
+        // what's 'up' on screen depends on how the camera is rotated
+        let screen = screen_rotation(&data, *camera_mode, pt).inverse();
+
+        let mut order: Vec<(Entity, f32)> = enemy_q.iter()
+            .map(|(entity, et)| {
+                let p2e = et.translation - pt.translation;
+                let key = match data.lock_order {
+                    LockOrder::Distance => p2e.length_squared(),
+                    LockOrder::Angle => {
+                        let on_screen = screen.mul_vec3(p2e);
+                        on_screen.x.atan2(on_screen.y).rem_euclid(TAU)
+                    }
+                };
+                (entity, key)
+            })
+            .collect();
+        order.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let next = match lock.0.and_then(|locked| order.iter().position(|(e, _)| *e == locked)) {
+            Some(i) => order.get((i + 1) % order.len()),
+            None => order.first(),
+        };
+        lock.0 = next.map(|(e, _)| *e);
+    }
+}
+
+
+pub fn drop_dead_target(
+    enemy_q: Query<(), (With<Enemy>, Without<Dying>)>,
+    mut lock_q: Query<&mut TargetLock>,
+) {
+    for mut lock in &mut lock_q {
+        if lock.0.is_some_and(|e| enemy_q.get(e).is_err()) {
+            lock.0 = None;
+        }
+    }
+}
+
+
+pub fn draw_reticle(
+    mut gizmos: Gizmos,
+    data: Res<GameData>,
+    lock_q: Query<&TargetLock, With<Player>>,
+    enemy_q: Query<&Transform, (With<Enemy>, Without<Dying>)>,
+) {
+    let Ok(lock) = lock_q.get_single() else { return; };
+    let Some(et) = lock.0.and_then(|e| enemy_q.get(e).ok()) else { return; };
+
+    let color = Color::rgb(8.0, 1.0, 1.0);
+    let r = data.lock_reticle_radius;
+    let center = et.translation.truncate();
+
+    gizmos.circle_2d(center, r, color);
+    for dir in [Vec2::X, Vec2::Y, Vec2::NEG_X, Vec2::NEG_Y] {
+        gizmos.line_2d(center + dir * r * 0.6, center + dir * r * 1.3, color);
+    }
+}
@@ -1,6 +1,10 @@
 #![allow(dead_code)]
 use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
 use bevy::prelude::*;
+use bevy::sprite::Material2dPlugin;
+use gamedata::{GameData, GameRng, GameState};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
 
 mod gamedata;
 mod bullets;
@@ -11,38 +15,145 @@ mod animations;
 mod camera;
 mod guns;
 mod ui;
+mod pickups;
+mod lockon;
+mod difficulty;
+mod menu;
+mod scores;
+mod input;
+mod replay;
+mod effects;
+mod level;
+mod anim_graph;
+mod atlas;
+mod vfx;
+mod damage_numbers;
 
 
 fn main() {
-    App::new()
-        .add_plugins( 
-            (
-                DefaultPlugins,
-                FrameTimeDiagnosticsPlugin
-                //FrameTimeDiagnosticsPlugin::default()
-            )
-        ) 
-        .init_resource::<gamedata::GameData>()
-        .add_systems(Startup, 
+    let mut app = App::new();
+    app.add_plugins( 
+        (
+            DefaultPlugins,
+            FrameTimeDiagnosticsPlugin,
+            //FrameTimeDiagnosticsPlugin::default()
+            Material2dPlugin::<bullets::BulletMaterial>::default(),
+        )
+    );
+
+    // after the plugins so bad arguments get reported through the log
+    // picking the difficulty on the command line skips the menu, and so does watching a replay
+    let replay = match replay::ReplayFile::from_args() {
+        Some(Ok(replay)) => Some(replay),
+        Some(Err(e)) => {
+            error!("{e}");
+            std::process::exit(1);
+        },
+        None => None,
+    };
+    let difficulty = replay.as_ref().map(|replay| replay.difficulty).or_else(difficulty::Difficulty::from_args);
+    let seed = replay.as_ref().map(|replay| replay.seed).unwrap_or_else(|| rand::thread_rng().gen());
+    let gamedata = GameData::default();
+    let camera_config = camera::CameraConfig::default();
+
+    match replay {
+        Some(replay) => app.insert_resource(replay::Replay::new(replay)),
+        None => app.insert_resource(replay::Recorder::new(seed)),
+    };
+
+    app
+        .insert_state(if difficulty.is_some() { GameState::InGame } else { GameState::Menu })
+        .insert_resource(difficulty.unwrap_or_default())
+        // the simulation steps in fixed ticks of `dt` so replays play out exactly the same
+        .insert_resource(Time::<Fixed>::from_seconds(gamedata.dt as f64))
+        .insert_resource(gamedata)
+        .insert_resource(camera_config.mode)
+        .insert_resource(camera_config)
+        .insert_resource(GameRng(StdRng::seed_from_u64(seed)))
+        .init_resource::<pickups::PickupData>()
+        .init_resource::<difficulty::DifficultyPresets>()
+        .init_resource::<input::Controls>()
+        .init_resource::<input::ActionState>()
+        .init_resource::<effects::EffectsConfig>()
+        .init_resource::<level::Level>()
+        .add_event::<effects::Impact>()
+        .add_event::<animations::AnimationEvent>()
+        .init_resource::<vfx::VfxConfig>()
+        .init_resource::<bullets::BulletStyles>()
+        .init_resource::<ui::UiConfig>()
+        .add_event::<vfx::Vfx>()
+        .init_resource::<damage_numbers::DamageNumbersConfig>()
+        .add_event::<damage_numbers::Damage>()
+        .add_systems(Startup, camera::spawn)
+        .add_systems(OnEnter(GameState::Menu), menu::spawn)
+        .add_systems(OnExit(GameState::Menu), menu::despawn)
+        .add_systems(OnEnter(GameState::InGame), 
             (
                 ui::spawn, 
+                level::spawn_backgrounds,
                 player::spawn, 
                 enemies::spawn,
-                camera::spawn
+            ).run_if(not(any_with_component::<player::Player>)) // coming back from the pause menu
+        )
+        .add_systems(OnEnter(GameState::Pause), menu::spawn_pause)
+        .add_systems(OnExit(GameState::Pause), menu::despawn_pause)
+        .add_systems(OnEnter(GameState::Rebinding), menu::spawn_rebinding)
+        .add_systems(OnExit(GameState::Rebinding), menu::despawn_rebinding)
+        .add_systems(FixedPreUpdate, 
+            (
+                input::update_actions.run_if(not(resource_exists::<replay::Replay>)),
+                replay::record.run_if(resource_exists::<replay::Recorder>.and_then(in_state(GameState::InGame))),
+                replay::feed.run_if(resource_exists::<replay::Replay>.and_then(in_state(GameState::InGame))),
+            ).chain()
+        )
+        .add_systems(Update, 
+            (menu::navigate, menu::update).chain().run_if(in_state(GameState::Menu))
+        )
+        .add_systems(FixedUpdate, 
+            menu::toggle_pause.run_if(in_state(GameState::InGame).or_else(in_state(GameState::Pause)))
+        )
+        .add_systems(Update, 
+            (
+                menu::pause_to_rebinding.run_if(in_state(GameState::Pause)),
+                (menu::rebind, menu::update_rebinding).chain().run_if(in_state(GameState::Rebinding)),
             )
         )
-        .add_systems(Update,
+        .add_systems(FixedUpdate,
             (
-                health::quit_on_player_death,
+                health::finish_dying,
+                health::invulnerability,
                 player::handle_movement,
-                animations::animate.after(player::handle_movement),
-                camera::follow_player,
+                level::clamp_player.after(player::handle_movement),
+                (anim_graph::run, animations::pick_direction).after(player::handle_movement).after(bullets::handle).before(animations::animate),
+                animations::animate.before(guns::enemy_guns).before(guns::player_guns),
                 health::handle,
-                (guns::enemy_guns, guns::player_guns, bullets::handle).after(health::handle),
-                ui::update,
-                bevy::window::close_on_esc
+                enemies::boss_phases.after(bullets::handle),
+                (guns::enemy_guns, guns::player_guns, bullets::handle, pickups::handle).after(health::handle),
+                guns::player_arsenal.before(guns::player_guns),
+                camera::cycle_mode.before(player::handle_movement),
+                (lockon::drop_dead_target, lockon::cycle_target).chain().before(guns::player_guns),
+            ).run_if(in_state(GameState::InGame))
+        )
+        .add_systems(Update,
+            (
+                (camera::follow_player, effects::trigger, effects::apply, level::scroll_backgrounds).chain(),
+                lockon::draw_reticle,
+                level::draw_border,
+                (vfx::spawn, vfx::update).chain(),
+                (damage_numbers::spawn, damage_numbers::update).chain(),
+                bullets::draw_trails,
+                guns::draw_telegraphs,
+                (ui::attach_bars, ui::update).chain(),
+            ).run_if(in_state(GameState::InGame))
+        )
+        .add_systems(Update, bevy::window::close_on_esc)
+        .add_systems(Last, 
+            (
+                scores::record_on_exit.run_if(not(resource_exists::<replay::Replay>)),
+                replay::save_on_exit.run_if(resource_exists::<replay::Recorder>),
             )
-        ).run();
+        )
+        .run();
 }
 
 
@@ -0,0 +1,284 @@
+use crate::damage_numbers::DamageNumbersConfig;
+use crate::difficulty::Difficulty;
+use crate::gamedata::GameState;
+use crate::input::{Action, ActionState, Binding, Controls};
+use bevy::prelude::*;
+
+// ==========
+// COMPONENTS
+// ==========
+
+#[derive(Component)]
+pub struct MenuText;
+
+// =======
+// SYSTEMS
+// =======
+
+pub fn spawn(
+    mut commands: Commands, 
+    asset_server: Res<AssetServer>
+) {
+    let style = TextStyle {
+        font: asset_server.load("Minecraft.ttf"),
+        font_size: 24.0,
+        color: Color::WHITE,
+    };
+
+    let mut sections = vec![TextSection::new("Select difficulty\n\n", style.clone())];
+    for difficulty in Difficulty::ALL {
+        sections.push(TextSection::new(format!("{}\n", difficulty.name()), style.clone()));
+    }
+    // filled in by `update`
+    sections.push(TextSection::from_style(style.clone()));
+    sections.push(TextSection::new("\nW / S to choose, D for damage numbers, Enter to start", style.clone()));
+
+    commands.spawn((
+        MenuText,
+        TextBundle {
+            text: Text {
+                justify: JustifyText::Center,
+                sections,
+                ..default()
+            },
+            style: Style {
+                margin: UiRect::all(Val::Auto),
+                ..default()
+            },
+            ..default()
+        }
+    ));
+}
+
+
+pub fn navigate(
+    k: Res<ButtonInput<KeyCode>>,
+    mut difficulty: ResMut<Difficulty>,
+    mut damage_numbers: ResMut<DamageNumbersConfig>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let i = Difficulty::ALL.iter().position(|d| d == difficulty.as_ref()).unwrap();
+    let n = Difficulty::ALL.len();
+
+    if k.any_just_pressed([KeyCode::KeyW, KeyCode::ArrowUp]) { 
+        *difficulty = Difficulty::ALL[(i + n - 1) % n]; 
+    }
+    if k.any_just_pressed([KeyCode::KeyS, KeyCode::ArrowDown]) { 
+        *difficulty = Difficulty::ALL[(i + 1) % n]; 
+    }
+    if k.just_pressed(KeyCode::KeyD) {
+        damage_numbers.enabled = !damage_numbers.enabled;
+    }
+    if k.any_just_pressed([KeyCode::Enter, KeyCode::Space]) { 
+        next_state.set(GameState::InGame); 
+    }
+}
+
+
+pub fn update(
+    difficulty: Res<Difficulty>,
+    damage_numbers: Res<DamageNumbersConfig>,
+    mut text_q: Query<&mut Text, With<MenuText>>,
+) {
+    for mut text in &mut text_q {
+        for (i, d) in Difficulty::ALL.iter().enumerate() {
+            // first section is the title
+            text.sections[i + 1].style.color = if d == difficulty.as_ref() { Color::GOLD } else { Color::GRAY };
+        }
+        // right after the difficulties
+        let toggle = &mut text.sections[Difficulty::ALL.len() + 1];
+        toggle.value = format!("\nDamage numbers: {}\n", if damage_numbers.enabled { "on" } else { "off" });
+    }
+}
+
+
+pub fn despawn(mut commands: Commands, menu_q: Query<Entity, With<MenuText>>) {
+    for entity in &menu_q {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+
+// =====
+// PAUSE
+// =====
+
+#[derive(Component)]
+pub struct PauseText;
+
+pub fn toggle_pause(
+    actions: Res<ActionState>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !actions.just_pressed(Action::Pause) { return; }
+
+    match state.get() {
+        GameState::InGame => next_state.set(GameState::Pause),
+        GameState::Pause => next_state.set(GameState::InGame),
+        _ => {}
+    }
+}
+
+
+pub fn spawn_pause(
+    mut commands: Commands, 
+    asset_server: Res<AssetServer>,
+    controls: Res<Controls>,
+) {
+    let style = TextStyle {
+        font: asset_server.load("Minecraft.ttf"),
+        font_size: 24.0,
+        color: Color::WHITE,
+    };
+    let pause_keys = controls.bindings(Action::Pause).iter().map(Binding::name).collect::<Vec<_>>().join(" / ");
+
+    commands.spawn((
+        PauseText,
+        TextBundle {
+            text: Text::from_section(format!("Paused\n\n{pause_keys} to resume, R to change controls"), style)
+                .with_justify(JustifyText::Center),
+            style: Style {
+                margin: UiRect::all(Val::Auto),
+                ..default()
+            },
+            ..default()
+        }
+    ));
+}
+
+
+pub fn pause_to_rebinding(
+    k: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if k.just_pressed(KeyCode::KeyR) {
+        next_state.set(GameState::Rebinding);
+    }
+}
+
+
+pub fn despawn_pause(mut commands: Commands, pause_q: Query<Entity, With<PauseText>>) {
+    for entity in &pause_q {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+
+// =========
+// REBINDING
+// =========
+
+#[derive(Component)]
+pub struct RebindText;
+
+/// which action is selected, and whether the next key pressed gets bound to it
+#[derive(Resource, Default)]
+pub struct RebindCursor {
+    pub index: usize,
+    pub listening: bool,
+}
+
+pub fn spawn_rebinding(
+    mut commands: Commands, 
+    asset_server: Res<AssetServer>
+) {
+    let style = TextStyle {
+        font: asset_server.load("Minecraft.ttf"),
+        font_size: 20.0,
+        color: Color::WHITE,
+    };
+
+    // title, one line per action, footer
+    let mut sections = vec![TextSection::new("Controls\n\n", style.clone())];
+    for _ in Action::ALL {
+        sections.push(TextSection::from_style(style.clone()));
+    }
+    sections.push(TextSection::from_style(style.clone()));
+
+    commands.init_resource::<RebindCursor>();
+    commands.spawn((
+        RebindText,
+        TextBundle {
+            text: Text {
+                justify: JustifyText::Left,
+                sections,
+                ..default()
+            },
+            style: Style {
+                margin: UiRect::all(Val::Auto),
+                ..default()
+            },
+            ..default()
+        }
+    ));
+}
+
+
+pub fn rebind(
+    k: Res<ButtonInput<KeyCode>>,
+    pad: Res<ButtonInput<GamepadButton>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut cursor: ResMut<RebindCursor>,
+    mut controls: ResMut<Controls>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let action = Action::ALL[cursor.index];
+
+    if cursor.listening {
+        let pressed = k.get_just_pressed().next().map(|key| Binding::Key(*key))
+            .or_else(|| pad.get_just_pressed().next().map(|button| Binding::GamepadButton(button.button_type)))
+            .or_else(|| mouse.get_just_pressed().next().map(|button| Binding::Mouse(*button)));
+
+        if let Some(binding) = pressed {
+            let bindings = controls.actions.entry(action).or_default();
+            if !bindings.contains(&binding) {
+                bindings.push(binding);
+            }
+            controls.save();
+            cursor.listening = false;
+        }
+        return;
+    }
+
+    let n = Action::ALL.len();
+    if k.any_just_pressed([KeyCode::KeyW, KeyCode::ArrowUp]) { cursor.index = (cursor.index + n - 1) % n; }
+    if k.any_just_pressed([KeyCode::KeyS, KeyCode::ArrowDown]) { cursor.index = (cursor.index + 1) % n; }
+    if k.just_pressed(KeyCode::Enter) { cursor.listening = true; }
+    if k.any_just_pressed([KeyCode::Delete, KeyCode::Backspace]) {
+        controls.actions.insert(action, Vec::new());
+        controls.save();
+    }
+    if k.just_pressed(KeyCode::Tab) { next_state.set(GameState::Pause); }
+}
+
+
+pub fn update_rebinding(
+    cursor: Res<RebindCursor>,
+    controls: Res<Controls>,
+    mut text_q: Query<&mut Text, With<RebindText>>,
+) {
+    for mut text in &mut text_q {
+        for (i, action) in Action::ALL.iter().enumerate() {
+            let keys = controls.bindings(*action).iter().map(Binding::name).collect::<Vec<_>>().join(", ");
+            let section = &mut text.sections[i + 1];
+            section.value = format!("{action:?}: {keys}\n");
+            section.style.color = if i == cursor.index { Color::GOLD } else { Color::GRAY };
+        }
+
+        let footer = text.sections.last_mut().unwrap();
+        footer.value = if cursor.listening {
+            format!("\npress a key, mouse or gamepad button for {:?}", Action::ALL[cursor.index])
+        } else {
+            String::from("\nW / S to choose, Enter to add a key, Delete to clear, Tab to go back")
+        };
+    }
+}
+
+
+pub fn despawn_rebinding(mut commands: Commands, rebind_q: Query<Entity, With<RebindText>>) {
+    for entity in &rebind_q {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<RebindCursor>();
+}
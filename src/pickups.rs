@@ -0,0 +1,182 @@
+use crate::player::{Player, PlayerStats};
+use crate::health::Health;
+use crate::gamedata::{GameData, GameRng};
+use crate::level::Level;
+
+use bevy::prelude::*;
+use bevy::sprite::MaterialMesh2dBundle;
+use serde::Deserialize;
+use rand::Rng;
+use std::fs::File;
+use std::io::BufReader;
+
+// =======
+// STRUCTS
+// =======
+
+#[derive(Copy, Clone, PartialEq, Deserialize)]
+pub enum PickupKind {
+    Power, Point, Bomb, Health
+}
+
+/// One row of an enemy's drop table: `count` pickups of `kind` worth `amount` each,
+/// dropped with probability `chance` when the enemy dies
+#[derive(Clone, Deserialize)]
+pub struct DropEntry {
+    pub kind: PickupKind,
+    pub amount: i32,
+    pub count: u32,
+    pub chance: f32,
+}
+
+#[derive(Component, Clone, Default, Deserialize)]
+pub struct Drops(pub Vec<DropEntry>);
+
+#[derive(Deserialize)]
+pub struct PickupLook {
+    pub kind: PickupKind,
+    pub size: f32,
+    pub color_r: f32,
+    pub color_g: f32,
+    pub color_b: f32,
+}
+
+#[derive(Resource, Deserialize)]
+pub struct PickupData {
+    pub launch_speed: f32,
+    pub scatter: f32,
+    pub gravity: f32,
+    pub fall_speed: f32,
+    pub magnet_radius: f32,
+    pub magnet_speed: f32,
+    pub collect_line_y: f32,
+    pub looks: Vec<PickupLook>,
+}
+
+impl Default for PickupData {
+    fn default() -> PickupData {
+        let file = File::open("config/pickups.json").unwrap();
+        let reader = BufReader::new(file);
+        let data: PickupData = serde_json::from_reader(reader).unwrap();
+        data
+    }
+}
+
+impl PickupData {
+    fn look(&self, kind: PickupKind) -> &PickupLook {
+        self.looks.iter().find(|look| look.kind == kind).unwrap()
+    }
+}
+
+// ==========
+// COMPONENTS
+// ==========
+
+#[derive(Component)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    pub amount: i32,
+    pub size: f32,
+    pub vel: Vec2,
+    pub magnetized: bool, // once it starts flying to the player it never stops
+}
+
+impl Pickup {
+    /// rolls every row of the drop table and spawns whatever comes out around `pos`
+    pub fn spawn_drops(
+        drops: &Drops,
+        pos: Vec3,
+        data: &PickupData,
+        rng: &mut GameRng,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        materials: &mut ResMut<Assets<ColorMaterial>>
+    ) {
+        for drop in drops.0.iter() {
+            if rng.gen::<f32>() >= drop.chance { continue; }
+            let look = data.look(drop.kind);
+
+            for _ in 0..drop.count {
+                let offset = Vec2::new(
+                    rng.gen_range(-data.scatter..=data.scatter),
+                    rng.gen_range(-data.scatter..=data.scatter)
+                );
+
+                commands.spawn((
+                    Pickup {
+                        kind: drop.kind,
+                        amount: drop.amount,
+                        size: look.size,
+                        vel: Vec2::new(offset.x, data.launch_speed),
+                        magnetized: false,
+                    },
+                    MaterialMesh2dBundle {
+                        mesh: meshes.add(Rectangle::new(look.size * 2.0, look.size * 2.0)).into(),
+                        material: materials.add(ColorMaterial::from(Color::rgb(look.color_r, look.color_g, look.color_b))),
+                        transform: Transform {
+                            translation: pos + offset.extend(0.0),
+                            rotation: Quat::from_rotation_z(f32::to_radians(45.0)),
+                            ..default()
+                        },
+                        ..default()
+                    }
+                ));
+            }
+        }
+    }
+}
+
+// =======
+// SYSTEMS
+// =======
+
+pub fn handle(
+    t: Res<Time>,
+    data: Res<GameData>,
+    pickup_data: Res<PickupData>,
+    level: Res<Level>,
+    mut commands: Commands,
+    mut player_q: Query<(&Transform, &mut Health, &mut PlayerStats), With<Player>>,
+    mut pickups: Query<(Entity, &mut Transform, &mut Pickup), Without<Player>>,
+) {
+    let dt = t.delta_seconds();
+    let mut player = player_q.get_single_mut().ok();
+
+    for (entity, mut kt, mut pickup) in &mut pickups {
+        if let Some((pt, health, stats)) = player.as_mut() {
+            let to_player = pt.translation.truncate() - kt.translation.truncate();
+            let close = to_player.length() <= pickup_data.magnet_radius;
+            let above_line = pt.translation.y >= pickup_data.collect_line_y;
+            if close || above_line { pickup.magnetized = true; }
+
+            // collect on hitbox contact, same test the bullets use
+            if to_player.length() <= pickup.size + pt.scale.x {
+                match pickup.kind {
+                    PickupKind::Power => stats.power += pickup.amount as u32,
+                    PickupKind::Point => stats.score += pickup.amount as u64,
+                    PickupKind::Bomb => stats.bombs += pickup.amount as u32,
+                    PickupKind::Health => health.0 = (health.0 + pickup.amount).min(data.player_health),
+                }
+                commands.entity(entity).despawn();
+                continue;
+            }
+
+            if pickup.magnetized {
+                pickup.vel = to_player.normalize_or_zero() * pickup_data.magnet_speed;
+            }
+        } else {
+            pickup.magnetized = false;
+        }
+
+        if !pickup.magnetized {
+            pickup.vel.x *= 1.0 - (dt * 4.0).min(1.0);
+            pickup.vel.y = (pickup.vel.y - pickup_data.gravity * dt).max(-pickup_data.fall_speed);
+        }
+
+        kt.translation += (pickup.vel * dt).extend(0.0);
+
+        if !level.arena.contains(kt.translation.truncate(), level.cull_margin) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -1,10 +1,12 @@
-use crate::health::Health;
-use crate::gamedata::GameData;
-use crate::animations::{TopDownStates, AnimationState, AnimationTimer, SpriteSheetConfig};
-use crate::guns::{
-    Guns, 
-    Gun
-};
+use crate::health::{Health, MaxHealth, Lives, Dying};
+use crate::gamedata::{GameData, ControlScheme, Facing};
+use crate::camera::{CameraMode, screen_rotation};
+use crate::input::ActionState;
+use crate::animations::{TopDownStates, AnimationState, AnimationTimer, SpriteSheetConfig, DirectionalSprite};
+use crate::anim_graph::AnimationParams;
+use crate::guns::Arsenal;
+use crate::lockon::TargetLock;
+use crate::vfx::{Vfx, VfxKind};
 use bevy::prelude::*;
 
 // =======
@@ -18,6 +20,14 @@ use bevy::prelude::*;
 #[derive(Component)]
 pub struct Player;
 
+/// what the player has collected so far
+#[derive(Component, Default)]
+pub struct PlayerStats {
+    pub power: u32,
+    pub score: u64,
+    pub bombs: u32,
+}
+
 
 // =======
 // SYSTEMS
@@ -28,74 +38,115 @@ pub fn spawn(
     gamedata: Res<GameData>,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+    mut vfx: EventWriter<Vfx>,
 ) {
-    let sheet = SpriteSheetConfig::player();
+    let sheet = SpriteSheetConfig::new(&gamedata.player_sprites);
+    let animations = sheet.animations();
+    let first_frame = animations.get(TopDownStates::Prone.as_ref()).map_or(0, |clip| clip.first);
+    let state = AnimationState::new(TopDownStates::Prone, &animations);
+    let arsenal = Arsenal::player();
+    let directional = sheet.directional.clone();
 
-    commands.spawn((
+    let mut player = commands.spawn((
         Player,
-        Health(3000),
-        Guns::new(vec![Gun::player_gun()]),
+        PlayerStats::default(),
+        Health(gamedata.player_health),
+        MaxHealth(gamedata.player_health),
+        Lives(gamedata.player_lives.max(1)),
+        arsenal.guns(),
+        arsenal,
+        TargetLock::default(),
         // sprites / animation stuff
-        sheet.animations.clone(), 
-        AnimationState::new(TopDownStates::Prone, false),
+        animations, 
+        state,
+        sheet.graph(),
+        AnimationParams::default(),
         AnimationTimer(Timer::from_seconds(sheet.duration_s, TimerMode::Repeating)),
         SpriteSheetBundle {
             transform: Transform::from_scale(Vec3::splat(gamedata.player_size)),
-            texture: asset_server.load(sheet.path),
+            texture: asset_server.load(sheet.path.clone()),
             atlas: TextureAtlas { 
-                index: sheet.animations.0.get("Prone").unwrap().first,
-                layout: texture_atlases.add(TextureAtlasLayout::from_grid(
-                    Vec2::new(sheet.frame_width, sheet.frame_height), 
-                    sheet.columns, 
-                    sheet.rows,
-                    Some(Vec2::new(sheet.padding_x, sheet.padding_y)), 
-                    None
-                ))
+                index: first_frame,
+                layout: texture_atlases.add(sheet.layout())
             },
             ..default()
         },
     ));
+    if let Some(directional) = directional {
+        player.insert(DirectionalSprite::new(directional));
+    }
+    vfx.send(Vfx { kind: VfxKind::PlayerSpawn, pos: Vec2::ZERO, target: Some(player.id()) });
 }
 
 
+/// signed angle from the player's current facing to `dir`, in -PI..PI
+#[inline]
+fn angle_to(rotation: Quat, dir: Vec2) -> f32 {
+    let facing = rotation.mul_vec3(Vec3::Y).truncate();
+    facing.angle_between(dir)
+}
+
+
+#[allow(clippy::type_complexity)]
 pub fn handle_movement(
     time: Res<Time>,
     gamedata: Res<GameData>,
-    k: Res<ButtonInput<KeyCode>>,
-    mut player: Query<(&mut Transform, &mut AnimationState), With<Player>>,
+    actions: Res<ActionState>,
+    camera_mode: Res<CameraMode>,
+    mut player: Query<(&mut Transform, &mut AnimationParams), (With<Player>, Without<Dying>)>,
 ) {
-    if let Ok((mut pt, mut state)) = player.get_single_mut() {
-        let mut direction = Vec3::ZERO;
-        let mut rotation_factor = 0.0;
-        let forward = pt.rotation.mul_vec3(Vec3::Y);
-        let right = pt.rotation.mul_vec3(Vec3::X);
-
-        let a_press = k.pressed(KeyCode::KeyA);
-        let w_press = k.pressed(KeyCode::KeyW);
-        let s_press = k.pressed(KeyCode::KeyS);
-        let d_press = k.pressed(KeyCode::KeyD);
-        let l_press = k.pressed(KeyCode::KeyL);
-        let quote_press = k.pressed(KeyCode::Quote);
-        let awsd_press = a_press || w_press || s_press || d_press;
-
-        if w_press { direction += forward; } 
-        if a_press { direction -= right; } 
-        if s_press { direction -= forward; } 
-        if d_press { direction += right; } 
-        if quote_press { rotation_factor -= 1.0; }
-        if l_press { rotation_factor += 1.0; }
-
-        if quote_press { state.change_if_its_not(TopDownStates::TurningRight); } 
-        else if l_press { state.change_if_its_not(TopDownStates::TurningLeft); } 
-        else if awsd_press { state.change_if_its_not(TopDownStates::Moving); } 
-        else { state.change_if_its_not(TopDownStates::Prone); } 
-
-        if direction.length() > 0.0 { 
-            direction = direction.normalize(); 
-        }
-
-        let rotation = rotation_factor * gamedata.player_rotation_speed * time.delta_seconds();
-        pt.rotate_z(rotation);
+    if let Ok((mut pt, mut params)) = player.get_single_mut() {
+        let screen_rotation = screen_rotation(&gamedata, *camera_mode, &pt);
+        let turn_by_keys = actions.rotation * gamedata.player_rotation_speed * time.delta_seconds();
+
+        // which way the movement input's up and right point in the world
+        let basis = match gamedata.control_scheme {
+            ControlScheme::Tank => pt.rotation,
+            ControlScheme::Screen | ControlScheme::TwinStick => screen_rotation,
+        };
+        let forward = basis.mul_vec3(Vec3::Y);
+        let right = basis.mul_vec3(Vec3::X);
+
+        // turn toward the cursor, no faster than the player could turn by keys
+        let toward_cursor = || {
+            let cursor = actions.cursor;
+            let max_turn = gamedata.player_rotation_speed * time.delta_seconds();
+            match cursor {
+                Some(cursor) => angle_to(pt.rotation, cursor - pt.translation.truncate()).clamp(-max_turn, max_turn),
+                None => 0.0,
+            }
+        };
+
+        // how far to turn this frame, mouse aim overrides the rotation keys in every scheme
+        let rotation = match gamedata.control_scheme {
+            _ if gamedata.mouse_aim => toward_cursor(),
+            ControlScheme::Tank => turn_by_keys,
+            ControlScheme::Screen => match gamedata.facing {
+                Facing::Fixed(degrees) => {
+                    let dir = screen_rotation * Quat::from_rotation_z(degrees.to_radians());
+                    angle_to(pt.rotation, dir.mul_vec3(Vec3::Y).truncate())
+                },
+                Facing::Mouse => toward_cursor(),
+            },
+            ControlScheme::TwinStick => {
+                if actions.aim != Vec2::ZERO {
+                    angle_to(pt.rotation, screen_rotation.mul_vec3(actions.aim.extend(0.0)).truncate())
+                } else {
+                    turn_by_keys
+                }
+            }
+        };
+
+        // analog: a stick pushed halfway moves at half speed
+        let movement = actions.movement;
+        let direction = forward * movement.y + right * movement.x;
+
+        params.set("turning_right", rotation < -f32::EPSILON);
+        params.set("turning_left", rotation > f32::EPSILON);
+        params.set("moving", movement != Vec2::ZERO);
+
+        if rotation.is_finite() { pt.rotate_z(rotation); }
         pt.translation += direction * gamedata.player_speed * time.delta_seconds();
     }
 }
+
@@ -0,0 +1,181 @@
+use crate::difficulty::Difficulty;
+use crate::input::{Action, ActionState, TickInput};
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Every tick's input gets recorded along with the RNG seed and a hash of the config.
+// `--replay replays/<file>.json` feeds it back instead of reading the devices
+
+const REPLAY_DIR: &str = "replays";
+
+// =======
+// STRUCTS
+// =======
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayTick {
+    pub previous: Vec<Action>, // so just_pressed comes out the same too
+    pub input: TickInput,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReplayFile {
+    pub seed: u64,
+    pub config_hash: u64,
+    pub difficulty: Difficulty,
+    pub ticks: Vec<(u32, ReplayTick)>, // run-length encoded: how many ticks in a row had this input
+}
+
+impl ReplayFile {
+    /// `--replay <path>` on the command line. Refuses replays recorded with different config
+    pub fn from_args() -> Option<Result<ReplayFile, String>> {
+        let args: Vec<String> = std::env::args().collect();
+        let i = args.iter().position(|arg| arg == "--replay")?;
+        let path = match args.get(i + 1) {
+            Some(path) => path,
+            None => return Some(Err(String::from("--replay needs the path of a replay file"))),
+        };
+        Some(ReplayFile::load(path))
+    }
+
+    pub fn load(path: &str) -> Result<ReplayFile, String> {
+        let file = File::open(path).map_err(|e| format!("could not open replay {path}: {e}"))?;
+        let replay: ReplayFile = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("{path} is not a valid replay: {e}"))?;
+
+        let hash = config_hash();
+        if replay.config_hash != hash {
+            return Err(format!(
+                "{path} was recorded with different config files (hash {:016x}, current config is {:016x}), \
+                 it would not play out the same. Check out the config it was recorded with to watch it",
+                replay.config_hash, hash
+            ));
+        }
+        Ok(replay)
+    }
+}
+
+
+/// FNV-1a over the names and contents of the config files. controls.json is left out,
+/// key bindings don't change anything since replays store actions, not keys
+pub fn config_hash() -> u64 {
+    let mut paths: Vec<PathBuf> = fs::read_dir("config").unwrap()
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter(|path| path.file_name().is_some_and(|name| name != "controls.json"))
+        .collect();
+    paths.sort();
+
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        for byte in name.bytes().chain(fs::read(&path).unwrap()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+// =========
+// RESOURCES
+// =========
+
+#[derive(Resource)]
+pub struct Recorder {
+    pub seed: u64,
+    pub config_hash: u64,
+    pub ticks: Vec<(u32, ReplayTick)>,
+}
+
+impl Recorder {
+    pub fn new(seed: u64) -> Self {
+        Recorder { seed, config_hash: config_hash(), ticks: Vec::new() }
+    }
+}
+
+/// present only when watching a replay
+#[derive(Resource)]
+pub struct Replay {
+    pub ticks: Vec<(u32, ReplayTick)>,
+    pub run: usize,  // which run-length entry we're in
+    pub played: u32, // how many ticks of that entry are done
+}
+
+impl Replay {
+    pub fn new(file: ReplayFile) -> Self {
+        Replay { ticks: file.ticks, run: 0, played: 0 }
+    }
+}
+
+// =======
+// SYSTEMS
+// =======
+
+pub fn record(actions: Res<ActionState>, mut recorder: ResMut<Recorder>) {
+    // pausing isn't part of the simulation, a replay that paused itself would never resume
+    let strip = |mut pressed: Vec<Action>| { pressed.retain(|a| *a != Action::Pause); pressed };
+    let mut input = actions.snapshot();
+    input.pressed = strip(input.pressed);
+    let tick = ReplayTick { previous: strip(actions.previous()), input };
+
+    match recorder.ticks.last_mut() {
+        Some((count, last)) if *last == tick => *count += 1,
+        _ => recorder.ticks.push((1, tick)),
+    }
+}
+
+
+pub fn feed(
+    mut replay: ResMut<Replay>,
+    mut actions: ResMut<ActionState>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let replay = replay.as_mut();
+    match replay.ticks.get(replay.run) {
+        Some((count, tick)) => {
+            actions.restore(&tick.previous, &tick.input);
+            replay.played += 1;
+            if replay.played >= *count {
+                replay.run += 1;
+                replay.played = 0;
+            }
+        },
+        None => {
+            info!("replay finished");
+            exit.send(AppExit);
+        }
+    }
+}
+
+
+pub fn save_on_exit(
+    mut exit: EventReader<AppExit>,
+    difficulty: Res<Difficulty>,
+    recorder: Res<Recorder>,
+) {
+    if exit.read().next().is_none() || recorder.ticks.is_empty() { return; }
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let path = format!("{REPLAY_DIR}/{timestamp}.json");
+    let file = ReplayFile {
+        seed: recorder.seed,
+        config_hash: recorder.config_hash,
+        difficulty: *difficulty,
+        ticks: recorder.ticks.clone(),
+    };
+
+    let written = fs::create_dir_all(REPLAY_DIR)
+        .and_then(|_| File::create(&path))
+        .map_err(|e| e.to_string())
+        .and_then(|f| serde_json::to_writer(BufWriter::new(f), &file).map_err(|e| e.to_string()));
+    match written {
+        Ok(_) => info!("replay saved to {path}"),
+        Err(e) => error!("could not save replay to {path}: {e}"),
+    }
+}
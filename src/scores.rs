@@ -0,0 +1,59 @@
+use crate::difficulty::Difficulty;
+use crate::player::{Player, PlayerStats};
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCORES_PATH: &str = "scores.json";
+
+#[derive(Serialize, Deserialize)]
+pub struct ScoreRecord {
+    pub score: u64,
+    pub difficulty: Difficulty,
+    pub timestamp: u64, // unix seconds
+}
+
+impl ScoreRecord {
+    pub fn all() -> Vec<ScoreRecord> {
+        File::open(SCORES_PATH)
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(self) {
+        let mut records = ScoreRecord::all();
+        records.push(self);
+        match File::create(SCORES_PATH) {
+            Ok(file) => {
+                if let Err(e) = serde_json::to_writer_pretty(BufWriter::new(file), &records) {
+                    error!("could not write {SCORES_PATH}: {e}");
+                }
+            },
+            Err(e) => error!("could not create {SCORES_PATH}: {e}"),
+        }
+    }
+}
+
+// =======
+// SYSTEMS
+// =======
+
+pub fn record_on_exit(
+    mut exit: EventReader<AppExit>,
+    difficulty: Res<Difficulty>,
+    player_q: Query<&PlayerStats, With<Player>>,
+) {
+    if exit.read().next().is_none() { return; }
+
+    if let Ok(stats) = player_q.get_single() {
+        ScoreRecord {
+            score: stats.score,
+            difficulty: *difficulty,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        }.save();
+    }
+}
@@ -1,74 +1,327 @@
-use crate::enemies::Enemy;
+use crate::camera::{CameraMode, screen_rotation};
+use crate::enemies::{Boss, Enemy};
+use crate::gamedata::GameData;
 use crate::player::Player;
-use crate::health::Health;
+use crate::health::{Dying, Health, MaxHealth, Lives};
 use bevy::prelude::*;
 use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
+use bevy::sprite::Anchor;
 use bevy::text::BreakLineOn;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+
+// =======
+// STRUCTS
+// =======
+
+#[derive(Deserialize)]
+pub struct BarStyle {
+    pub width: f32,
+    pub height: f32,
+    pub fill_r: f32,
+    pub fill_g: f32,
+    pub fill_b: f32,
+    pub back_r: f32,
+    pub back_g: f32,
+    pub back_b: f32,
+}
+
+impl BarStyle {
+    fn fill(&self) -> Color { Color::rgb(self.fill_r, self.fill_g, self.fill_b) }
+    fn back(&self) -> Color { Color::rgb(self.back_r, self.back_g, self.back_b) }
+}
+
+/// Looks of the HUD. The enemy bars are in world units and float `enemy_bar_offset_y`
+/// above each enemy, everything else is in screen pixels
+#[derive(Resource, Deserialize)]
+pub struct UiConfig {
+    pub font: String,
+    pub font_size: f32,
+    pub enemy_bar: BarStyle,
+    pub enemy_bar_offset_y: f32,
+    pub boss_bar: BarStyle,
+    pub boss_marker_r: f32,
+    pub boss_marker_g: f32,
+    pub boss_marker_b: f32,
+    pub player_bar: BarStyle,
+}
+
+impl Default for UiConfig {
+    fn default() -> UiConfig {
+        let file = File::open("config/ui.json").unwrap();
+        let reader = BufReader::new(file);
+        let data: UiConfig = serde_json::from_reader(reader).unwrap();
+        data
+    }
+}
+
+// ==========
+// COMPONENTS
+// ==========
+
+#[derive(Component)]
+pub struct FpsText;
+
+/// floats over `owner`, `fill` is the child sprite that shrinks
+#[derive(Component)]
+pub struct EnemyBar {
+    pub owner: Entity,
+    pub fill: Entity,
+}
+
+/// where the boss bars stack up, top of the screen
+#[derive(Component)]
+pub struct BossBars;
+
+#[derive(Component)]
+pub struct BossBar {
+    pub owner: Entity,
+    pub fill: Entity,
+}
+
+#[derive(Component)]
+pub struct PlayerHealthFill;
+
+#[derive(Component)]
+pub struct LivesText;
+
+#[inline]
+fn fraction(health: &Health, max: &MaxHealth) -> f32 {
+    (health.0 as f32 / max.0.max(1) as f32).clamp(0.0, 1.0)
+}
+
+// =======
+// SYSTEMS
+// =======
 
 pub fn spawn(
-    mut commands: Commands, 
-    asset_server: Res<AssetServer>
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<UiConfig>,
 ) {
     let white_style = TextStyle {
-        font: asset_server.load("Minecraft.ttf"),
-        font_size: 16.0,
-        color: Color::WHITE,
-    };
-
-    let gold_style = TextStyle { 
-        font: asset_server.load("Minecraft.ttf"),
-        font_size: 16.0,
+        font: asset_server.load(config.font.clone()),
+        font_size: config.font_size,
         color: Color::WHITE,
     };
 
-    commands.spawn(
+    commands.spawn((
+        FpsText,
         TextBundle {
             text: Text {
-                //alignment: TextAlignment::Center,
                 justify: JustifyText::Center,
                 linebreak_behavior: BreakLineOn::AnyCharacter,
                 sections: vec![
-                    TextSection::from_style(gold_style.clone()),
-                    TextSection::new(" FPS ", white_style.clone()), // changed from white_style.clone() -> no need to clone right? just inmutable borrow
-                    TextSection::new(" Player Health: ", white_style.clone()),
-                    TextSection::from_style(gold_style.clone()), 
-                    TextSection::new(" Boss Health: ", white_style.clone()),
-                    TextSection::from_style(gold_style.clone())
+                    TextSection::from_style(white_style.clone()),
+                    TextSection::new(" FPS ", white_style.clone()),
                 ],
             },
             style: Style {
-                display: Display::Flex,
-                flex_direction: FlexDirection::Row,
-                flex_wrap: FlexWrap::Wrap,
-                justify_content: JustifyContent::SpaceBetween,
-                align_content: AlignContent::Stretch,
-                align_items: AlignItems::Stretch,
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            ..default()
+        }
+    ));
+
+    commands.spawn((
+        BossBars,
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(8.0),
+                width: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(6.0),
                 ..default()
             },
             ..default()
         }
-    );
+    ));
+
+    // player health and lives, bottom left
+    let bar = &config.player_bar;
+    commands.spawn(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(8.0),
+            bottom: Val::Px(8.0),
+            align_items: AlignItems::Center,
+            column_gap: Val::Px(8.0),
+            ..default()
+        },
+        ..default()
+    }).with_children(|panel| {
+        panel.spawn(TextBundle::from_section("HP", white_style.clone()));
+        panel.spawn(NodeBundle {
+            style: Style { width: Val::Px(bar.width), height: Val::Px(bar.height), ..default() },
+            background_color: bar.back().into(),
+            ..default()
+        }).with_children(|back| {
+            back.spawn((
+                PlayerHealthFill,
+                NodeBundle {
+                    style: Style { width: Val::Percent(100.0), height: Val::Percent(100.0), ..default() },
+                    background_color: bar.fill().into(),
+                    ..default()
+                }
+            ));
+        });
+        panel.spawn((LivesText, TextBundle::from_section("", white_style.clone())));
+    });
+}
+
+
+/// gives every new enemy its bar, a world bar over its head or a boss bar up top
+pub fn attach_bars(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<UiConfig>,
+    enemies: Query<(Entity, Option<&Boss>), Added<Enemy>>,
+    boss_bars: Query<Entity, With<BossBars>>,
+) {
+    for (owner, boss) in &enemies {
+        match boss {
+            None => {
+                let bar = &config.enemy_bar;
+                let mut fill = Entity::PLACEHOLDER;
+                commands.spawn(SpriteBundle {
+                    sprite: Sprite { color: bar.back(), custom_size: Some(Vec2::new(bar.width, bar.height)), ..default() },
+                    transform: Transform::from_xyz(0.0, 0.0, 5.0),
+                    ..default()
+                }).with_children(|back| {
+                    fill = back.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color: bar.fill(),
+                            custom_size: Some(Vec2::new(bar.width, bar.height)),
+                            anchor: Anchor::CenterLeft,
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(-bar.width / 2.0, 0.0, 0.1),
+                        ..default()
+                    }).id();
+                }).insert(EnemyBar { owner, fill });
+            },
+            Some(boss) => {
+                let Ok(root) = boss_bars.get_single() else { continue; };
+                let bar = &config.boss_bar;
+                let marker = Color::rgb(config.boss_marker_r, config.boss_marker_g, config.boss_marker_b);
+                let style = TextStyle {
+                    font: asset_server.load(config.font.clone()),
+                    font_size: config.font_size,
+                    color: Color::WHITE,
+                };
+
+                let mut fill = Entity::PLACEHOLDER;
+                let boss_bar = commands.spawn(NodeBundle {
+                    style: Style { flex_direction: FlexDirection::Column, align_items: AlignItems::Center, ..default() },
+                    ..default()
+                }).with_children(|column| {
+                    column.spawn(TextBundle::from_section(boss.name.clone(), style));
+                    column.spawn(NodeBundle {
+                        style: Style { width: Val::Px(bar.width), height: Val::Px(bar.height), ..default() },
+                        background_color: bar.back().into(),
+                        ..default()
+                    }).with_children(|back| {
+                        fill = back.spawn(NodeBundle {
+                            style: Style { width: Val::Percent(100.0), height: Val::Percent(100.0), ..default() },
+                            background_color: bar.fill().into(),
+                            ..default()
+                        }).id();
+                        // one tick where each phase starts, drawn over the fill
+                        for phase in boss.phases.iter() {
+                            back.spawn(NodeBundle {
+                                style: Style {
+                                    position_type: PositionType::Absolute,
+                                    left: Val::Percent(phase.clamp(0.0, 1.0) * 100.0),
+                                    width: Val::Px(2.0),
+                                    height: Val::Percent(100.0),
+                                    ..default()
+                                },
+                                background_color: marker.into(),
+                                z_index: ZIndex::Local(1),
+                                ..default()
+                            });
+                        }
+                    });
+                }).id();
+                commands.entity(boss_bar).insert(BossBar { owner, fill });
+                commands.entity(root).add_child(boss_bar);
+            },
+        }
+    }
 }
 
 
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn update(
     diagnostics: Res<DiagnosticsStore>,  // changed from Res<Diagnostics>
-    mut text_query: Query<&mut Text>, 
-    player_health_query: Query<&Health, With<Player>>,
-    boss_health_query: Query<&Health, With<Enemy>>
+    config: Res<UiConfig>,
+    gamedata: Res<GameData>,
+    camera_mode: Res<CameraMode>,
+    mut commands: Commands,
+    mut fps_text: Query<&mut Text, (With<FpsText>, Without<LivesText>)>,
+    mut lives_text: Query<&mut Text, (With<LivesText>, Without<FpsText>)>,
+    player_q: Query<(&Transform, Option<&Health>, &MaxHealth, Option<&Lives>, Has<Dying>), (With<Player>, Without<EnemyBar>)>,
+    owners: Query<(&Transform, &Health, &MaxHealth), Without<EnemyBar>>,
+    enemy_bars: Query<(Entity, &EnemyBar)>,
+    boss_bars: Query<(Entity, &BossBar)>,
+    mut transforms: Query<&mut Transform, With<EnemyBar>>,
+    mut sprites: Query<&mut Sprite>,
+    mut nodes: Query<&mut Style>,
+    player_fill: Query<Entity, With<PlayerHealthFill>>,
 ) {
-    for mut text in &mut text_query {
+    for mut text in &mut fps_text {
         if let Some(fps) = diagnostics.get(&FrameTimeDiagnosticsPlugin::FPS) {
             if let Some(value) = fps.smoothed() {
                 text.sections[0].value = format!("{value:.2}");
             }
         }
-        if let Ok(health) = player_health_query.get_single() {
-            text.sections[3].value = health.0.to_string();
+    }
+
+    let mut screen = Quat::IDENTITY;
+    if let Ok((pt, health, max, lives, dying)) = player_q.get_single() {
+        screen = screen_rotation(&gamedata, *camera_mode, pt);
+        // the killing hit takes the player's Health away, the bar still has to empty
+        let (health, lives) = match health {
+            Some(health) if !dying => (fraction(health, max), lives.map_or(1, |lives| lives.0)),
+            _ => (0.0, 0),
+        };
+        for fill in &player_fill {
+            if let Ok(mut style) = nodes.get_mut(fill) {
+                style.width = Val::Percent(health * 100.0);
+            }
         }
+        for mut text in &mut lives_text {
+            text.sections[0].value = format!("x{lives}");
+        }
+    }
+
+    for (entity, bar) in &enemy_bars {
+        let Ok((owner, health, max)) = owners.get(bar.owner) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        // above the enemy on screen, not in the world, when the camera turns with the player
+        if let Ok(mut t) = transforms.get_mut(entity) {
+            let offset = screen.mul_vec3(Vec3::new(0.0, config.enemy_bar_offset_y, 0.0)).truncate();
+            t.translation = (owner.translation.truncate() + offset).extend(5.0);
+            t.rotation = screen;
+        }
+        if let Ok(mut sprite) = sprites.get_mut(bar.fill) {
+            sprite.custom_size = Some(Vec2::new(config.enemy_bar.width * fraction(health, max), config.enemy_bar.height));
+        }
+    }
 
-        for health in &boss_health_query {
-            text.sections[5].value = health.0.to_string();
+    for (entity, bar) in &boss_bars {
+        let Ok((_, health, max)) = owners.get(bar.owner) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        if let Ok(mut style) = nodes.get_mut(bar.fill) {
+            style.width = Val::Percent(fraction(health, max) * 100.0);
         }
     }
 }
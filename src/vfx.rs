@@ -0,0 +1,218 @@
+use bevy::prelude::*;
+use bevy::sprite::MaterialMesh2dBundle;
+use serde::Deserialize;
+use rand::Rng;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+// Purely visual, nothing in here feeds back into the simulation, so it runs on the
+// frame and can use any randomness it likes. Colors above 1 glow through the bloom
+
+// =======
+// STRUCTS
+// =======
+
+/// `BulletCancel` is a bullet taken away before it hit anything, its shooter died
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub enum VfxKind {
+    EnemyDeath, BulletImpact, PlayerHit, BulletCancel, EnemySpawn, PlayerSpawn
+}
+
+/// something happened at `pos`, `target` is who gets flashed
+#[derive(Event, Copy, Clone)]
+pub struct Vfx {
+    pub kind: VfxKind,
+    pub pos: Vec2,
+    pub target: Option<Entity>,
+}
+
+#[derive(Deserialize)]
+pub struct ParticleBurst {
+    pub count: u32,
+    pub speed_min: f32,
+    pub speed_max: f32,
+    pub size: f32,
+    pub lifetime_s: f32,
+    pub drag: f32, // fraction of speed lost per second
+    pub color_r: f32,
+    pub color_g: f32,
+    pub color_b: f32,
+}
+
+#[derive(Deserialize)]
+pub struct RingConfig {
+    pub start_radius: f32,
+    pub end_radius: f32,
+    pub lifetime_s: f32,
+    pub color_r: f32,
+    pub color_g: f32,
+    pub color_b: f32,
+}
+
+#[derive(Deserialize)]
+pub struct FlashConfig {
+    pub duration_s: f32,
+    pub color_r: f32,
+    pub color_g: f32,
+    pub color_b: f32,
+}
+
+/// everything one kind of event shows, any part can be left out
+#[derive(Deserialize)]
+pub struct VfxEffect {
+    #[serde(default)]
+    pub particles: Option<ParticleBurst>,
+    #[serde(default)]
+    pub ring: Option<RingConfig>,
+    #[serde(default)]
+    pub flash: Option<FlashConfig>,
+}
+
+#[derive(Resource, Deserialize)]
+pub struct VfxConfig {
+    pub enabled: bool,
+    pub effects: HashMap<VfxKind, VfxEffect>,
+}
+
+impl Default for VfxConfig {
+    fn default() -> VfxConfig {
+        let file = File::open("config/vfx.json").unwrap();
+        let reader = BufReader::new(file);
+        let data: VfxConfig = serde_json::from_reader(reader).unwrap();
+        data
+    }
+}
+
+// ==========
+// COMPONENTS
+// ==========
+
+#[derive(Component)]
+pub struct Particle {
+    pub vel: Vec2,
+    pub drag: f32,
+    pub timer: Timer,
+}
+
+#[derive(Component)]
+pub struct Ring {
+    pub start_radius: f32,
+    pub end_radius: f32,
+    pub color: Color,
+    pub timer: Timer,
+}
+
+/// tints the sprite toward `color` and lets it fade back to normal
+#[derive(Component)]
+pub struct Flash {
+    pub color: Color,
+    pub timer: Timer,
+}
+
+// =======
+// SYSTEMS
+// =======
+
+pub fn spawn(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    config: Res<VfxConfig>,
+    mut events: EventReader<Vfx>,
+    sprites: Query<(), With<Sprite>>,
+) {
+    if !config.enabled { events.clear(); return; }
+    let mut rng = rand::thread_rng();
+
+    for event in events.read() {
+        let Some(effect) = config.effects.get(&event.kind) else { continue; };
+
+        if let Some(burst) = &effect.particles {
+            // one mesh and material per burst, the particles shrink instead of fading
+            let mesh = meshes.add(Circle::new(burst.size));
+            let material = materials.add(ColorMaterial::from(Color::rgb(burst.color_r, burst.color_g, burst.color_b)));
+            for _ in 0..burst.count {
+                let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                let speed = rng.gen_range(burst.speed_min..=burst.speed_max.max(burst.speed_min));
+                commands.spawn((
+                    Particle {
+                        vel: Vec2::from_angle(angle) * speed,
+                        drag: burst.drag,
+                        timer: Timer::from_seconds(burst.lifetime_s, TimerMode::Once),
+                    },
+                    MaterialMesh2dBundle {
+                        mesh: mesh.clone().into(),
+                        material: material.clone(),
+                        transform: Transform::from_translation(event.pos.extend(1.0)),
+                        ..default()
+                    },
+                ));
+            }
+        }
+
+        if let Some(ring) = &effect.ring {
+            commands.spawn((
+                Ring {
+                    start_radius: ring.start_radius,
+                    end_radius: ring.end_radius,
+                    color: Color::rgb(ring.color_r, ring.color_g, ring.color_b),
+                    timer: Timer::from_seconds(ring.lifetime_s, TimerMode::Once),
+                },
+                TransformBundle::from_transform(Transform::from_translation(event.pos.extend(0.0))),
+            ));
+        }
+
+        if let (Some(flash), Some(target)) = (&effect.flash, event.target) {
+            // the target may have died this same tick
+            if sprites.contains(target) {
+                commands.entity(target).insert(Flash {
+                    color: Color::rgb(flash.color_r, flash.color_g, flash.color_b),
+                    timer: Timer::from_seconds(flash.duration_s, TimerMode::Once),
+                });
+            }
+        }
+    }
+}
+
+
+pub fn update(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut gizmos: Gizmos,
+    mut particles: Query<(Entity, &mut Transform, &mut Particle)>,
+    mut rings: Query<(Entity, &Transform, &mut Ring), Without<Particle>>,
+    mut flashes: Query<(Entity, &mut Sprite, &mut Flash)>,
+) {
+    let dt = time.delta_seconds();
+
+    for (entity, mut t, mut particle) in &mut particles {
+        particle.timer.tick(time.delta());
+        if particle.timer.finished() { commands.entity(entity).despawn(); continue; }
+
+        let slow_down = (1.0 - particle.drag * dt).max(0.0);
+        particle.vel *= slow_down;
+        t.translation += (particle.vel * dt).extend(0.0);
+        t.scale = Vec3::splat(1.0 - particle.timer.fraction());
+    }
+
+    for (entity, t, mut ring) in &mut rings {
+        ring.timer.tick(time.delta());
+        if ring.timer.finished() { commands.entity(entity).despawn(); continue; }
+
+        let progress = ring.timer.fraction();
+        let radius = ring.start_radius + (ring.end_radius - ring.start_radius) * progress;
+        gizmos.circle_2d(t.translation.truncate(), radius, ring.color.with_a(1.0 - progress)).segments(48);
+    }
+
+    for (entity, mut sprite, mut flash) in &mut flashes {
+        flash.timer.tick(time.delta());
+        let left = flash.timer.fraction_remaining();
+        let [r, g, b, _] = flash.color.as_rgba_f32();
+        sprite.color = Color::rgb(1.0 + (r - 1.0) * left, 1.0 + (g - 1.0) * left, 1.0 + (b - 1.0) * left);
+        if flash.timer.finished() {
+            sprite.color = Color::WHITE;
+            commands.entity(entity).remove::<Flash>();
+        }
+    }
+}